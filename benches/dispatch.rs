@@ -0,0 +1,157 @@
+// Measures how quickly the VM dispatches instructions, in nanoseconds per executed instruction.
+// VM::interpret_chunk() verifies a chunk before running it, so the time taken by verification
+// alone is measured too, and subtracted to give the time spent running the chunk.
+//
+// The compiler folds expressions on literals into a single constant, and there are no variables
+// or loops yet, so the workloads are built as bytecode directly: long, straight-line sequences of
+// instructions that keep the stack shallow.
+//
+// Each workload is run as it's built, and again after the peephole optimizer has fused its
+// instructions into superinstructions. The optimized chunk executes fewer instructions, so compare
+// the run times of the two rather than the times per instruction.
+//
+// Run it with `cargo bench --bench dispatch`. The VM prints the result of each run, which is
+// expected; the timings are reported at the end.
+
+use rust_clox::chunk::{Chunk, OpCode};
+use rust_clox::optimizer::optimize;
+use rust_clox::value::Value;
+use rust_clox::verifier::verify;
+use rust_clox::vm::{Config, VM};
+use std::time::{Duration, Instant};
+
+const REPEATS: usize = 200_000;
+const RUNS: usize = 20;
+
+struct Workload {
+    name: &'static str,
+    // Writes one repetition of the workload's instructions.
+    body: fn(&mut Chunk),
+    // Writes the instructions that start the workload, leaving a value on the stack.
+    start: fn(&mut Chunk),
+}
+
+fn main() {
+    let workloads = [
+        Workload {
+            name: "arithmetic",
+            start: |chunk| constant(chunk, 1.0),
+            body: |chunk| {
+                for op_code in [
+                    OpCode::Add,
+                    OpCode::Multiply,
+                    OpCode::Subtract,
+                    OpCode::Divide,
+                ] {
+                    constant(chunk, 1.5);
+                    op(chunk, op_code);
+                }
+            },
+        },
+        Workload {
+            name: "comparison",
+            start: |chunk| op(chunk, OpCode::True),
+            body: |chunk| {
+                constant(chunk, 1.0);
+                constant(chunk, 2.0);
+                op(chunk, OpCode::Less);
+                op(chunk, OpCode::Equal);
+                op(chunk, OpCode::Not);
+            },
+        },
+        Workload {
+            name: "literals",
+            start: |chunk| op(chunk, OpCode::Nil),
+            body: |chunk| {
+                op(chunk, OpCode::Not);
+                op(chunk, OpCode::False);
+                op(chunk, OpCode::Equal);
+                op(chunk, OpCode::Nil);
+                op(chunk, OpCode::Equal);
+            },
+        },
+        Workload {
+            name: "negation",
+            start: |chunk| constant(chunk, 1.0),
+            body: |chunk| {
+                op(chunk, OpCode::Negate);
+                op(chunk, OpCode::Negate);
+                op(chunk, OpCode::Negate);
+                constant(chunk, 2.0);
+                op(chunk, OpCode::Add);
+            },
+        },
+    ];
+
+    let mut results = Vec::new();
+    for (workload, optimized) in workloads
+        .iter()
+        .flat_map(|workload| [(workload, false), (workload, true)])
+    {
+        let mut best_verify = Duration::MAX;
+        let mut best_total = Duration::MAX;
+        let mut instruction_count = 0;
+        for _ in 0..RUNS {
+            let mut chunk = build(workload);
+            if optimized {
+                optimize(&mut chunk);
+            }
+            instruction_count = chunk.instructions().count();
+
+            let start = Instant::now();
+            verify(&chunk).expect("benchmark chunk should be valid");
+            best_verify = best_verify.min(start.elapsed());
+
+            let mut vm = VM::with_config("", Config::default());
+            let start = Instant::now();
+            vm.interpret_chunk(chunk)
+                .expect("benchmark chunk should run");
+            best_total = best_total.min(start.elapsed());
+        }
+        let best_run = best_total.saturating_sub(best_verify);
+        results.push((
+            workload.name,
+            optimized,
+            instruction_count,
+            best_verify,
+            best_run,
+        ));
+    }
+
+    println!();
+    println!(
+        "{:>12} {:>9} {:>14} {:>12} {:>12} {:>16}",
+        "workload", "peephole", "instructions", "verify", "run", "run/instruction"
+    );
+    for (name, optimized, instruction_count, verify, run) in results {
+        println!(
+            "{:>12} {:>9} {:>14} {:>9.2} ms {:>9.2} ms {:>13.2} ns",
+            name,
+            if optimized { "yes" } else { "no" },
+            instruction_count,
+            verify.as_secs_f64() * 1e3,
+            run.as_secs_f64() * 1e3,
+            run.as_nanos() as f64 / instruction_count as f64,
+        );
+    }
+}
+
+fn build(workload: &Workload) -> Chunk {
+    let mut chunk = Chunk::new();
+    (workload.start)(&mut chunk);
+    for _ in 0..REPEATS {
+        (workload.body)(&mut chunk);
+    }
+    op(&mut chunk, OpCode::Return);
+    chunk
+}
+
+fn op(chunk: &mut Chunk, op_code: OpCode) {
+    chunk.write_byte(op_code.to_u8(), 1);
+}
+
+fn constant(chunk: &mut Chunk, number: f64) {
+    chunk
+        .write_constant(Value::number(number), 1)
+        .expect("benchmark chunk should have room for its constants");
+}
@@ -0,0 +1,59 @@
+// Reports how much memory the run-length encoded line table saves, compared to storing the line
+// of every byte of code, and how long it takes to look lines up in it.
+//
+// Run it with `cargo bench --bench line_table`.
+
+use rust_clox::chunk::{Chunk, LineRun};
+use rust_clox::compiler::compile;
+use std::hint::black_box;
+use std::mem::size_of;
+use std::time::Instant;
+
+fn main() {
+    println!(
+        "{:>8} {:>10} {:>14} {:>14} {:>8} {:>14}",
+        "lines", "code", "per-byte table", "run table", "saving", "get_line"
+    );
+    for line_count in [1_000, 10_000, 100_000] {
+        let chunk = compile(&script(line_count)).expect("benchmark script should compile");
+
+        let per_byte_size = chunk.code.len() * size_of::<usize>();
+        let run_size = size_of_val(chunk.line_runs());
+        let lookup_time = time_lookups(&chunk);
+
+        println!(
+            "{:>8} {:>8} B {:>12} B {:>12} B {:>7.1}x {:>11.1} ns",
+            line_count,
+            chunk.code.len(),
+            per_byte_size,
+            run_size,
+            per_byte_size as f64 / run_size as f64,
+            lookup_time,
+        );
+    }
+    println!(
+        "\nEach run takes {} bytes, and there's one run per source line that emits code.",
+        size_of::<LineRun>()
+    );
+}
+
+// The compiler only handles single expressions so far, so a large script is one long expression
+// spread over many lines, with a few operations on each. Strings are mixed in with the numbers so
+// that constant folding can't reduce the script to a single constant.
+fn script(line_count: usize) -> String {
+    let mut source = "(1 + \"a\") * (2 - \"b\") / (3 < \"c\") +\n".repeat(line_count - 1);
+    source.push('1');
+    source
+}
+
+// Returns the average time of a Chunk::get_line call, in nanoseconds.
+fn time_lookups(chunk: &Chunk) -> f64 {
+    const ROUNDS: usize = 10;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for offset in 0..chunk.code.len() {
+            black_box(chunk.get_line(black_box(offset)));
+        }
+    }
+    start.elapsed().as_nanos() as f64 / (ROUNDS * chunk.code.len()) as f64
+}
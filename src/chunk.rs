@@ -1,9 +1,11 @@
 use crate::value::Value;
-use std::fmt;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::{fmt, io};
 
-#[derive(Copy, Clone, Debug, PartialEq, Hash)]
-pub(crate) enum OpCode {
+/// The instructions of the VM's bytecode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OpCode {
     Constant = 0,
     Nil = 1,
     True = 2,
@@ -18,6 +20,11 @@ pub(crate) enum OpCode {
     Not = 11,
     Negate = 12,
     Return = 13,
+    ConstantLong = 14,
+    NotEqual = 15,
+    GreaterEqual = 16,
+    LessEqual = 17,
+    AddConstant = 18,
 }
 
 impl OpCode {
@@ -37,6 +44,11 @@ impl OpCode {
             11 => Some(OpCode::Not),
             12 => Some(OpCode::Negate),
             13 => Some(OpCode::Return),
+            14 => Some(OpCode::ConstantLong),
+            15 => Some(OpCode::NotEqual),
+            16 => Some(OpCode::GreaterEqual),
+            17 => Some(OpCode::LessEqual),
+            18 => Some(OpCode::AddConstant),
             _ => None,
         }
     }
@@ -44,12 +56,138 @@ impl OpCode {
     pub fn to_u8(self) -> u8 {
         self as u8
     }
+
+    /// The name of the opcode, as shown by the disassembler.
+    pub fn name(self) -> &'static str {
+        match self {
+            OpCode::Constant => "OP_CONSTANT",
+            OpCode::Nil => "OP_NIL",
+            OpCode::True => "OP_TRUE",
+            OpCode::False => "OP_FALSE",
+            OpCode::Equal => "OP_EQUAL",
+            OpCode::Greater => "OP_GREATER",
+            OpCode::Less => "OP_LESS",
+            OpCode::Add => "OP_ADD",
+            OpCode::Subtract => "OP_SUBTRACT",
+            OpCode::Multiply => "OP_MULTIPLY",
+            OpCode::Divide => "OP_DIVIDE",
+            OpCode::Not => "OP_NOT",
+            OpCode::Negate => "OP_NEGATE",
+            OpCode::Return => "OP_RETURN",
+            OpCode::ConstantLong => "OP_CONSTANT_LONG",
+            OpCode::NotEqual => "OP_NOT_EQUAL",
+            OpCode::GreaterEqual => "OP_GREATER_EQUAL",
+            OpCode::LessEqual => "OP_LESS_EQUAL",
+            OpCode::AddConstant => "OP_ADD_CONSTANT",
+        }
+    }
+
+    /// The number of operand bytes that follow the opcode in the bytecode.
+    pub fn operand_count(self) -> usize {
+        match self {
+            OpCode::Constant | OpCode::AddConstant => 1,
+            OpCode::ConstantLong => 3,
+            _ => 0,
+        }
+    }
+
+    /// The number of values that the instruction pops off the stack, followed by the number of
+    /// values that it pushes onto it.
+    pub fn stack_effect(self) -> (usize, usize) {
+        match self {
+            OpCode::Constant
+            | OpCode::ConstantLong
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False => (0, 1),
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => (2, 1),
+            OpCode::Not | OpCode::Negate | OpCode::AddConstant => (1, 1),
+            OpCode::Return => (1, 0),
+        }
+    }
 }
 
-pub(crate) struct Chunk {
+impl Display for OpCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// OP_CONSTANT_LONG stores the index of its constant in 24 bits.
+const MAX_CONSTANTS: usize = 1 << 24;
+
+/// A chunk of compiled bytecode, along with the line of each byte and the constants it uses.
+///
+/// Its Display implementation disassembles the whole chunk.
+pub struct Chunk {
     pub code: Vec<u8>,
-    pub lines: Vec<usize>,
+    // Consecutive bytes nearly always come from the same line, so rather than storing the line of
+    // every byte, the line table is run-length encoded. It must always cover exactly the bytes in
+    // `code`, which is why it's kept private.
+    lines: Vec<LineRun>,
     pub constants: Vec<Value>,
+    // The index of each number and string constant added with add_constant, so that identical
+    // constants share a slot.
+    constant_indices: HashMap<ConstantKey, usize>,
+}
+
+// Numbers are compared by their bits rather than with ==, so that 0 and -0 stay separate
+// constants and NaN can be found at all. Every NaN has the same bits in a key, as Lox can't tell
+// them apart.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    String(String),
+}
+
+impl ConstantKey {
+    fn new(value: &Value) -> Option<ConstantKey> {
+        if let Some(number) = value.as_number() {
+            Some(ConstantKey::Number(number_bits(number)))
+        } else {
+            value
+                .as_str()
+                .map(|string| ConstantKey::String(string.to_string()))
+        }
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            ConstantKey::Number(bits) => value.as_number().map(number_bits) == Some(*bits),
+            ConstantKey::String(string) => value.as_str() == Some(string),
+        }
+    }
+}
+
+fn number_bits(number: f64) -> u64 {
+    if number.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        number.to_bits()
+    }
+}
+
+/// A run of consecutive bytes of a chunk's code that all come from the same source line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LineRun {
+    pub line: usize,
+    /// The offset just past the last byte of the run, which is also where the next run starts.
+    pub end: usize,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::new()
+    }
 }
 
 impl Chunk {
@@ -58,111 +196,378 @@ impl Chunk {
             code: Vec::new(),
             lines: Vec::new(),
             constants: Vec::new(),
+            constant_indices: HashMap::new(),
         }
     }
 
-    pub fn constants(&self) -> &Vec<Value> {
-        &self.constants
-    }
-
-    pub fn write_op_code(&mut self, op_code: OpCode, line: usize) {
+    pub(crate) fn write_op_code(&mut self, op_code: OpCode, line: usize) {
         self.write_byte(op_code.to_u8(), line);
     }
 
     pub fn write_byte(&mut self, byte: u8, line: usize) {
         self.code.push(byte);
-        self.lines.push(line);
+        self.push_line_run(line, 1);
     }
 
-    pub fn add_constant(&mut self, value: Value) -> usize {
-        self.constants.push(value);
-        self.constants.len() - 1
+    /// Records that the next `count` bytes of code come from the given line.
+    pub(crate) fn push_line_run(&mut self, line: usize, count: usize) {
+        let end = self.line_table_len() + count;
+        match self.lines.last_mut() {
+            Some(run) if run.line == line => run.end = end,
+            _ if count == 0 => {}
+            _ => self.lines.push(LineRun { line, end }),
+        }
     }
 
-    #[cfg(feature = "debug_trace_execution")]
-    pub fn disassemble(&self, name: &str) {
-        println!("== {} ==", name);
+    /// Removes the code from the given offset onwards, along with its lines.
+    pub(crate) fn truncate(&mut self, length: usize) {
+        self.code.truncate(length);
+        // Keep the runs that end before the new length, and the part of the next run, if any,
+        // that's before it.
+        let mut kept = self.lines.partition_point(|run| run.end < length);
+        let start = kept.checked_sub(1).map_or(0, |index| self.lines[index].end);
+        if start < length && kept < self.lines.len() {
+            self.lines[kept].end = length;
+            kept += 1;
+        }
+        self.lines.truncate(kept);
+    }
 
-        print!("{}", self);
+    /// Replaces the code and line table of this chunk with those of the given chunk, keeping this
+    /// chunk's constants.
+    pub(crate) fn replace_code(&mut self, other: Chunk) {
+        self.code = other.code;
+        self.lines = other.lines;
     }
 
-    #[cfg(feature = "debug_trace_execution")]
-    pub fn disassemble_instruction(&self, offset: usize) {
-        let mut buffer = String::new();
-        let _ = self.fmt_instruction(&mut buffer, offset);
-        print!("{buffer}");
+    /// Returns the source line of the byte at the given offset.
+    ///
+    /// Panics if the offset is past the end of the line table.
+    pub fn get_line(&self, offset: usize) -> usize {
+        match self.line_at(offset) {
+            Some(line) => line,
+            None => panic!("offset {offset} is past the end of the line table"),
+        }
     }
 
-    #[cfg(feature = "debug_trace_execution")]
-    fn fmt_instruction(&self, f: &mut dyn fmt::Write, offset: usize) -> Result<usize, fmt::Error> {
-        write!(f, "{:04} ", offset)?;
-        if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
-            write!(f, "   | ")?;
+    /// Returns the source line of the byte at the given offset, or None if the offset is past the
+    /// end of the line table. As `code` is public, code can be added to a chunk without lines.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        let index = self.lines.partition_point(|run| run.end <= offset);
+        self.lines.get(index).map(|run| run.line)
+    }
+
+    /// The run-length encoded line table, in the order of the code.
+    pub fn line_runs(&self) -> &[LineRun] {
+        &self.lines
+    }
+
+    /// The number of bytes of code that the line table covers.
+    pub(crate) fn line_table_len(&self) -> usize {
+        self.lines.last().map_or(0, |run| run.end)
+    }
+
+    /// Adds the given constant, unless the chunk already has an identical number or string
+    /// constant, and returns its index.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        if let Some(index) = self.find_constant(&value) {
+            return index;
+        }
+
+        let index = self.constants.len();
+        if let Some(key) = ConstantKey::new(&value) {
+            self.constant_indices.insert(key, index);
+        }
+        self.constants.push(value);
+        index
+    }
+
+    fn find_constant(&self, value: &Value) -> Option<usize> {
+        let key = ConstantKey::new(value)?;
+        let index = *self.constant_indices.get(&key)?;
+        // The constants are public, so they may have changed since the index was recorded.
+        let constant = self.constants.get(index)?;
+        key.matches(constant).then_some(index)
+    }
+
+    /// Adds the given constant, and writes the instruction that loads it: OP_CONSTANT for the
+    /// first 256 constants and OP_CONSTANT_LONG, whose operand is a 24-bit little-endian index,
+    /// for the rest.
+    ///
+    /// Returns the index of the constant, or None if the chunk already has the maximum of 2^24
+    /// constants.
+    pub fn write_constant(&mut self, value: Value, line: usize) -> Option<usize> {
+        if self.constants.len() >= MAX_CONSTANTS && self.find_constant(&value).is_none() {
+            return None;
+        }
+
+        let index = self.add_constant(value);
+        if let Ok(index) = u8::try_from(index) {
+            self.write_op_code(OpCode::Constant, line);
+            self.write_byte(index, line);
         } else {
-            write!(f, "{:4} ", self.lines[offset])?;
-        }
-
-        match OpCode::from_u8(self.code[offset]) {
-            Some(OpCode::Constant) => self.fmt_constant_instruction(f, "OP_CONSTANT", offset),
-            Some(OpCode::Nil) => self.fmt_simple_instruction(f, "OP_NIL", offset),
-            Some(OpCode::True) => self.fmt_simple_instruction(f, "OP_TRUE", offset),
-            Some(OpCode::False) => self.fmt_simple_instruction(f, "OP_FALSE", offset),
-            Some(OpCode::Equal) => self.fmt_simple_instruction(f, "OP_EQUAL", offset),
-            Some(OpCode::Greater) => self.fmt_simple_instruction(f, "OP_GREATER", offset),
-            Some(OpCode::Less) => self.fmt_simple_instruction(f, "OP_LESS", offset),
-            Some(OpCode::Add) => self.fmt_simple_instruction(f, "OP_ADD", offset),
-            Some(OpCode::Subtract) => self.fmt_simple_instruction(f, "OP_SUBTRACT", offset),
-            Some(OpCode::Multiply) => self.fmt_simple_instruction(f, "OP_MULTIPLY", offset),
-            Some(OpCode::Divide) => self.fmt_simple_instruction(f, "OP_DIVIDE", offset),
-            Some(OpCode::Not) => self.fmt_simple_instruction(f, "OP_NOT", offset),
-            Some(OpCode::Negate) => self.fmt_simple_instruction(f, "OP_NEGATE", offset),
-            Some(OpCode::Return) => self.fmt_simple_instruction(f, "OP_RETURN", offset),
-            _ => {
-                write!(f, "Unknown opcode {}", self.code[offset])?;
-                Ok(offset + 1)
+            self.write_op_code(OpCode::ConstantLong, line);
+            // MAX_CONSTANTS keeps the index within 24 bits.
+            for byte in &(index as u32).to_le_bytes()[..3] {
+                self.write_byte(*byte, line);
             }
         }
+        Some(index)
     }
 
-    #[cfg(feature = "debug_trace_execution")]
-    fn fmt_constant_instruction(
-        &self,
-        f: &mut dyn fmt::Write,
-        name: &str,
-        offset: usize,
-    ) -> Result<usize, fmt::Error> {
-        let constant = self.code[offset + 1];
-        write!(f, "{: <16} {:4} '", name, constant)?;
-        write!(f, "{}", self.constants[constant as usize])?;
-        writeln!(f, "'")?;
-        Ok(offset + 2)
+    /// Decodes the instructions of the chunk, in order.
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            chunk: self,
+            offset: 0,
+        }
+    }
+
+    /// Decodes the instruction that starts at the given offset, if the offset is in the chunk.
+    pub fn instruction(&self, offset: usize) -> Option<Instruction<'_>> {
+        let byte = *self.code.get(offset)?;
+        let op_code = OpCode::from_u8(byte);
+        let operand_count = op_code.map_or(0, OpCode::operand_count);
+        // A malformed chunk may end in the middle of an instruction.
+        let end = (offset + 1 + operand_count).min(self.code.len());
+        let operands = &self.code[offset + 1..end];
+        let constant =
+            constant_index(op_code, operands).and_then(|index| self.constants.get(index));
+        Some(Instruction {
+            offset,
+            line: self.line_at(offset),
+            byte,
+            op_code,
+            operands,
+            constant,
+        })
     }
 
-    #[cfg(feature = "debug_trace_execution")]
-    fn fmt_simple_instruction(
+    /// Writes the disassembly of the whole chunk, under a header with the given name.
+    pub fn disassemble(&self, name: &str, out: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(out, "== {name} ==")?;
+        write!(out, "{self}")
+    }
+
+    pub(crate) fn disassemble_instruction(
         &self,
-        f: &mut dyn fmt::Write,
-        name: &str,
         offset: usize,
-    ) -> Result<usize, fmt::Error> {
-        writeln!(f, "{name}")?;
-        Ok(offset + 1)
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        let mut buffer = String::new();
+        if let Some(instruction) = self.instruction(offset) {
+            let _ = self.fmt_instruction(&mut buffer, &instruction);
+        }
+        write!(out, "{buffer}")
+    }
+
+    fn fmt_instruction(&self, f: &mut dyn fmt::Write, instruction: &Instruction) -> fmt::Result {
+        let offset = instruction.offset;
+        write!(f, "{:04} ", offset)?;
+        match instruction.line {
+            Some(line) if offset > 0 && self.line_at(offset - 1) == Some(line) => {
+                write!(f, "   | ")?
+            }
+            line => write_line(f, line)?,
+        }
+        instruction.fmt_operation(f)?;
+        writeln!(f)
     }
 }
 
-#[cfg(feature = "debug_trace_execution")]
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let mut offset = 0;
-        while offset < self.code.len() {
-            offset = self.fmt_instruction(f, offset)?;
+        for instruction in self.instructions() {
+            self.fmt_instruction(f, &instruction)?;
         }
         Ok(())
     }
 }
 
-pub(crate) const NULL_CHUNK: Chunk = Chunk {
-    code: vec![],
-    lines: vec![],
-    constants: vec![],
-};
+/// An iterator over the decoded instructions of a [`Chunk`].
+pub struct Instructions<'a> {
+    chunk: &'a Chunk,
+    offset: usize,
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Instruction<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.chunk.instruction(self.offset)?;
+        self.offset += instruction.size();
+        Some(instruction)
+    }
+}
+
+/// A decoded bytecode instruction.
+///
+/// Its Display implementation formats it like a line of the disassembler's output.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Instruction<'a> {
+    /// The offset of the instruction's opcode in the chunk's code.
+    pub offset: usize,
+    /// The source line of the instruction, or None if the chunk's line table doesn't reach it.
+    pub line: Option<usize>,
+    /// The raw opcode byte.
+    pub byte: u8,
+    /// The opcode, or None if `byte` isn't a valid opcode.
+    pub op_code: Option<OpCode>,
+    /// The operand bytes that follow the opcode.
+    pub operands: &'a [u8],
+    /// The constant that the instruction loads, if it loads one.
+    pub constant: Option<&'a Value>,
+}
+
+impl Instruction<'_> {
+    /// The number of bytes that the instruction takes up in the chunk's code.
+    pub fn size(&self) -> usize {
+        1 + self.operands.len()
+    }
+
+    /// The index of the constant that the instruction loads, if it loads one.
+    pub fn constant_index(&self) -> Option<usize> {
+        constant_index(self.op_code, self.operands)
+    }
+
+    fn fmt_operation(&self, f: &mut dyn fmt::Write) -> fmt::Result {
+        let Some(op_code) = self.op_code else {
+            return write!(f, "Unknown opcode {}", self.byte);
+        };
+        match (self.constant_index(), self.constant) {
+            (Some(index), Some(constant)) => {
+                write!(f, "{: <16} {:4} '{}'", op_code.name(), index, constant)
+            }
+            (Some(index), None) => {
+                write!(f, "{: <16} {:4} <invalid constant>", op_code.name(), index)
+            }
+            _ => write!(f, "{}", op_code.name()),
+        }
+    }
+}
+
+impl Display for Instruction<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04} ", self.offset)?;
+        write_line(f, self.line)?;
+        self.fmt_operation(f)
+    }
+}
+
+// Writes the line of an instruction in the disassembler's output, or ? if it doesn't have one.
+fn write_line(f: &mut dyn fmt::Write, line: Option<usize>) -> fmt::Result {
+    match line {
+        Some(line) => write!(f, "{line:4} "),
+        None => write!(f, "   ? "),
+    }
+}
+
+fn constant_index(op_code: Option<OpCode>, operands: &[u8]) -> Option<usize> {
+    match (op_code?, operands) {
+        (OpCode::Constant | OpCode::AddConstant, [index]) => Some(*index as usize),
+        (OpCode::ConstantLong, [low, middle, high]) => {
+            Some(u32::from_le_bytes([*low, *middle, *high, 0]) as usize)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+impl Chunk {
+    /// Returns the opcode, operands and line of each instruction, for tests to compare with what
+    /// they expect. Checks that the line table covers all of the code, and that every byte of an
+    /// instruction is on the same line.
+    pub(crate) fn instruction_lines(&self) -> Vec<(OpCode, Vec<u8>, usize)> {
+        assert_eq!(self.line_table_len(), self.code.len());
+        self.instructions()
+            .map(|instruction| {
+                let line = instruction.line.unwrap();
+                for offset in instruction.offset..instruction.offset + instruction.size() {
+                    assert_eq!(self.get_line(offset), line);
+                }
+                let operands = instruction.operands.to_vec();
+                (instruction.op_code.unwrap(), operands, line)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_slots_between_identical_constants() {
+        let mut chunk = Chunk::new();
+        let constants = [
+            Value::number(0.0),
+            Value::number(-0.0),
+            Value::number(f64::NAN),
+            Value::number(-f64::NAN),
+            Value::number(f64::from_bits(f64::NAN.to_bits() | 1)),
+            Value::string("a".to_string()),
+            Value::string("a".to_string()),
+            Value::string("b".to_string()),
+            Value::number(0.0),
+        ];
+        let indices = constants.map(|value| chunk.add_constant(value));
+        assert_eq!(indices, [0, 1, 2, 2, 2, 3, 3, 4, 0]);
+        assert_eq!(chunk.constants.len(), 5);
+        assert!(chunk.constants[1].as_number().unwrap().is_sign_negative());
+    }
+
+    #[test]
+    fn doesnt_share_slots_that_have_changed() {
+        let mut chunk = Chunk::new();
+        assert_eq!(chunk.add_constant(Value::number(1.0)), 0);
+        assert_eq!(chunk.add_constant(Value::string("a".to_string())), 1);
+
+        chunk.constants.truncate(1);
+        assert_eq!(chunk.add_constant(Value::string("a".to_string())), 1);
+
+        chunk.constants[0] = Value::number(2.0);
+        assert_eq!(chunk.add_constant(Value::number(1.0)), 2);
+        assert_eq!(chunk.add_constant(Value::number(2.0)), 3);
+        chunk.constants.clear();
+        assert_eq!(chunk.add_constant(Value::number(1.0)), 0);
+    }
+
+    #[test]
+    fn reuses_constants_when_the_pool_is_full() {
+        let mut chunk = Chunk::new();
+        chunk.add_constant(Value::number(1.0));
+        chunk.add_constant(Value::string("a".to_string()));
+        chunk.constants.resize(MAX_CONSTANTS, Value::nil());
+
+        assert_eq!(chunk.write_constant(Value::number(1.0), 1), Some(0));
+        assert_eq!(
+            chunk.write_constant(Value::string("a".to_string()), 1),
+            Some(1)
+        );
+        assert_eq!(chunk.write_constant(Value::number(2.0), 1), None);
+        assert_eq!(chunk.write_constant(Value::nil(), 1), None);
+        assert_eq!(chunk.constants.len(), MAX_CONSTANTS);
+        assert_eq!(
+            chunk.instruction_lines(),
+            [
+                (OpCode::Constant, vec![0], 1),
+                (OpCode::Constant, vec![1], 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_pushed_without_a_line_has_no_line() {
+        let mut chunk = Chunk::new();
+        chunk.write_op_code(OpCode::Nil, 1);
+        chunk.code.push(OpCode::Return as u8);
+
+        assert_eq!(chunk.line_at(0), Some(1));
+        assert_eq!(chunk.line_at(1), None);
+        assert_eq!(chunk.instruction(1).unwrap().line, None);
+        assert_eq!(
+            format!("{chunk}"),
+            "0000    1 OP_NIL\n0001    ? OP_RETURN\n"
+        );
+    }
+}
@@ -1,7 +1,13 @@
 use crate::chunk::{Chunk, OpCode};
+use crate::optimizer::optimize;
 use crate::scanner::{Scanner, Token, TokenKind, NULL_TOKEN};
 use crate::value::Value;
 
+// How deeply parse_precedence() may recurse, which is about twice as deep as parentheses and unary
+// operators can be nested. The parser recurses on the native stack, so scripts that nest deeper
+// are reported as a compile error, rather than overflowing it and aborting the process.
+const MAX_PARSE_DEPTH: usize = 1024;
+
 struct Parser<'a> {
     current: Token<'a>,
     previous: Token<'a>,
@@ -91,17 +97,44 @@ impl<'a> ParseRule<'a> {
     }
 }
 
+/// Compiles the given source code into a chunk of bytecode, and optimizes it with
+/// [`optimize`].
+///
+/// Compile errors are reported to stderr, in which case None is returned.
+pub fn compile(source: &str) -> Option<Chunk> {
+    let mut chunk = Chunk::new();
+    if Compiler::new(source, &mut chunk).compile() {
+        optimize(&mut chunk);
+        Some(chunk)
+    } else {
+        None
+    }
+}
+
 pub(crate) struct Compiler<'a> {
-    source: &'a [u8],
     scanner: Scanner<'a>,
     parser: Parser<'a>,
     compiling_chunk: &'a mut Chunk,
+    // The last constant value that was emitted, so that operators whose operands are all
+    // constants can be folded into a single constant.
+    last_constant: Option<EmittedConstant>,
+    // How many calls to parse_precedence() are in progress.
+    depth: usize,
+}
+
+// A constant value, and where the code that loads it starts and ends.
+#[derive(Clone)]
+struct EmittedConstant {
+    start: usize,
+    end: usize,
+    // The number of constants in the pool before the code was emitted.
+    constant_count: usize,
+    value: Value,
 }
 
 impl<'a> Compiler<'a> {
-    pub fn new(source: &'a [u8], chunk: &'a mut Chunk) -> Compiler<'a> {
+    pub fn new(source: &'a str, chunk: &'a mut Chunk) -> Compiler<'a> {
         Compiler {
-            source,
             scanner: Scanner::new(source),
             parser: Parser {
                 current: NULL_TOKEN,
@@ -110,6 +143,8 @@ impl<'a> Compiler<'a> {
                 panic_mode: false,
             },
             compiling_chunk: chunk,
+            last_constant: None,
+            depth: 0,
         }
     }
 
@@ -131,11 +166,13 @@ impl<'a> Compiler<'a> {
 
         loop {
             self.parser.current = self.scanner.scan_token();
-            if self.parser.current.kind != TokenKind::Error {
-                break;
+            match self.parser.current.kind {
+                // Doc comments aren't attached to anything yet, as there are no declarations to
+                // document.
+                TokenKind::DocComment => {}
+                TokenKind::Error => self.error_at_current(self.parser.current.lexeme),
+                _ => break,
             }
-
-            self.error_at_current(&self.parser.current.lexeme_to_string());
         }
     }
 
@@ -163,33 +200,21 @@ impl<'a> Compiler<'a> {
             return;
         }
         self.parser.panic_mode = true;
+        self.parser.had_error = true;
         eprint!("[line {}] Error", token.line);
 
         match token.kind {
             TokenKind::Eof => eprint!(" at end"),
             TokenKind::Error => {}
-            _ => eprint!(" at {}", token.lexeme_to_string()),
+            _ => eprint!(" at {}", token.lexeme),
         }
 
         eprintln!(": {}", message);
     }
 
     fn emit_op_code(&mut self, op_code: OpCode) {
-        self.emit_byte(op_code.to_u8())
-    }
-
-    fn emit_op_codes(&mut self, first: OpCode, second: OpCode) {
-        self.emit_bytes(first.to_u8(), second.to_u8())
-    }
-
-    fn emit_byte(&mut self, byte: u8) {
         self.compiling_chunk
-            .write_byte(byte, self.parser.previous.line);
-    }
-
-    fn emit_bytes(&mut self, first: u8, second: u8) {
-        self.emit_byte(first);
-        self.emit_byte(second);
+            .write_op_code(op_code, self.parser.previous.line);
     }
 
     fn emit_return(&mut self) {
@@ -197,39 +222,60 @@ impl<'a> Compiler<'a> {
     }
 
     fn emit_constant(&mut self, value: Value) {
-        let constant = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant.to_u8(), constant)
-    }
-
-    fn make_constant(&mut self, value: Value) -> u8 {
-        let current_chunk = &mut self.compiling_chunk;
-        let constant = current_chunk.add_constant(value);
-        if constant > u8::MAX as usize {
-            // Note: in a real VM, we'd need another bytecode instruction like OP_CONSTANT_16
-            // that stores the constant index as two bytes, so that the VM could handle more
-            // than 256 constants when needed.
-            //
-            // The original clox doesn't support this, as apparently the code for doing this
-            // "isn't particularly illuminating". [1]
-            //
-            // [1] https://www.craftinginterpreters.com/compiling-expressions.html#parsers-for-tokens
-            //
-            // TODO: See how other Lox implementations do this.
+        // Note: the original clox can only refer to 256 constants per chunk, as OP_CONSTANT stores
+        // the index of its constant in a single byte. Doing better "isn't particularly
+        // illuminating" according to Robert Nystrom [1], but real-world scripts with large tables
+        // of literals need more, so Chunk::write_constant() switches to OP_CONSTANT_LONG, which
+        // stores the index in three bytes, past the first 256 constants. Identical numbers and
+        // strings also share a single constant, so repeated literals don't use up the pool.
+        //
+        // [1] https://www.craftinginterpreters.com/compiling-expressions.html#parsers-for-tokens
+        let line = self.parser.previous.line;
+        if self.compiling_chunk.write_constant(value, line).is_none() {
             self.error("Too many constants in one chunk.");
-            return 0u8;
         }
+    }
+
+    // Emits the instruction that loads the given value, which must be a literal or the result of
+    // folding literals.
+    fn emit_value(&mut self, value: Value) {
+        let start = self.compiling_chunk.code.len();
+        let constant_count = self.compiling_chunk.constants.len();
+        match value.as_bool() {
+            Some(true) => self.emit_op_code(OpCode::True),
+            Some(false) => self.emit_op_code(OpCode::False),
+            None if value.is_nil() => self.emit_op_code(OpCode::Nil),
+            None => self.emit_constant(value.clone()),
+        }
+        self.last_constant = Some(EmittedConstant {
+            start,
+            end: self.compiling_chunk.code.len(),
+            constant_count,
+            value,
+        });
+    }
 
-        constant as u8
+    // Returns the constant whose code was emitted last, if nothing has been emitted since.
+    fn trailing_constant(&self) -> Option<EmittedConstant> {
+        self.last_constant
+            .as_ref()
+            .filter(|constant| constant.end == self.compiling_chunk.code.len())
+            .cloned()
+    }
+
+    // Replaces the code of the given constant, and anything after it, with the code that loads
+    // the folded value.
+    fn replace_with_constant(&mut self, constant: &EmittedConstant, value: Value) {
+        self.compiling_chunk.truncate(constant.start);
+        // Constants that were added by the removed code can't be used anywhere else.
+        self.compiling_chunk
+            .constants
+            .truncate(constant.constant_count);
+        self.emit_value(value);
     }
 
     fn end_compiler(&mut self) {
         self.emit_return();
-        #[cfg(feature = "debug_print_code")]
-        {
-            if !self.parser.had_error {
-                self.compiling_chunk.disassemble("code");
-            }
-        }
     }
 
     fn binary(&mut self) {
@@ -259,26 +305,35 @@ impl<'a> Compiler<'a> {
         //
         // To enable that, we would call parsePrecedence() with the same precedence as the current
         // operator."
+        let left = self.trailing_constant();
         let precedence = rule.precedence.next();
         self.parse_precedence(precedence);
 
-        // Robert Nystrom says that for the match below below, more care would be required because
-        // IEEE 754 mandates that "all comparison operators return false when an operand is NaN.
-        // That means NaN <= 1 is false and NaN > 1 is also false. But our desugaring assumes the
-        // latter is always the negation of the former. For the book, we won’t get hung up on this,
-        // but these kinds of details will matter in your real language implementations."
-        //
-        // He also says that additional instructions for !=, <= and >= should be made for a real VM
-        // anyway if the goal is performance.
-        //
-        // TODO: Address this comment.
+        // If both operands are constants that were emitted one after the other, the expression
+        // can be evaluated now instead of at runtime. Anything that would be a runtime error, like
+        // -"a", isn't folded, so that it's still reported at runtime.
+        if let (Some(left), Some(right)) = (left, self.trailing_constant()) {
+            if left.end == right.start {
+                if let Some(value) = fold_binary(operator_kind, &left.value, &right.value) {
+                    self.replace_with_constant(&left, value);
+                    return;
+                }
+            }
+        }
+
+        // Note: clox desugars !=, <= and >= into the negation of ==, > and <. Robert Nystrom points
+        // out that this is wrong for NaN, because IEEE 754 mandates that "all comparison operators
+        // return false when an operand is NaN. That means NaN <= 1 is false and NaN > 1 is also
+        // false. But our desugaring assumes the latter is always the negation of the former." He
+        // also suggests dedicated instructions for a real VM for the sake of performance, so each
+        // operator has its own instruction here.
         match operator_kind {
-            TokenKind::BangEqual => self.emit_op_codes(OpCode::Equal, OpCode::Not),
+            TokenKind::BangEqual => self.emit_op_code(OpCode::NotEqual),
             TokenKind::EqualEqual => self.emit_op_code(OpCode::Equal),
             TokenKind::Greater => self.emit_op_code(OpCode::Greater),
-            TokenKind::GreaterEqual => self.emit_op_codes(OpCode::Less, OpCode::Not),
+            TokenKind::GreaterEqual => self.emit_op_code(OpCode::GreaterEqual),
             TokenKind::Less => self.emit_op_code(OpCode::Less),
-            TokenKind::LessEqual => self.emit_op_codes(OpCode::Greater, OpCode::Not),
+            TokenKind::LessEqual => self.emit_op_code(OpCode::LessEqual),
             TokenKind::Minus => self.emit_op_code(OpCode::Subtract),
             TokenKind::Plus => self.emit_op_code(OpCode::Add),
             TokenKind::Slash => self.emit_op_code(OpCode::Divide),
@@ -297,28 +352,24 @@ impl<'a> Compiler<'a> {
     }
 
     fn number(&mut self) {
-        let value = Value::Number(
-            self.parser
-                .previous
-                .lexeme_to_string()
-                .parse::<f64>()
-                .unwrap_or(0f64),
-        );
-        self.emit_constant(value);
+        match parse_number(self.parser.previous.lexeme) {
+            Ok(number) => self.emit_value(Value::number(number)),
+            Err(message) => self.error(message),
+        }
     }
 
     fn string(&mut self) {
-        let value = Value::String(
+        let value = Value::string(
             self.parser.previous.string_literal_lexeme_to_string()
         );
-        self.emit_constant(value);
+        self.emit_value(value);
     }
 
     fn literal(&mut self) {
-        self.emit_op_code(match self.parser.previous.kind {
-            TokenKind::False => OpCode::False,
-            TokenKind::Nil => OpCode::Nil,
-            TokenKind::True => OpCode::True,
+        self.emit_value(match self.parser.previous.kind {
+            TokenKind::False => Value::bool(false),
+            TokenKind::Nil => Value::nil(),
+            TokenKind::True => Value::bool(true),
             _ => unreachable!(),
         })
     }
@@ -354,6 +405,13 @@ impl<'a> Compiler<'a> {
         // "a.b + c", which does not follow the Lox specification.
         self.parse_precedence(Precedence::Unary);
 
+        if let Some(operand) = self.trailing_constant() {
+            if let Some(value) = fold_unary(operator_kind, &operand.value) {
+                self.replace_with_constant(&operand, value);
+                return;
+            }
+        }
+
         // Emit the operator instruction.
         match operator_kind {
             TokenKind::Bang => self.emit_op_code(OpCode::Not),
@@ -365,6 +423,18 @@ impl<'a> Compiler<'a> {
     // Parse only the subsequent tokens with a precedence greater than or equal to the given
     // precedence.
     fn parse_precedence(&mut self, precedence: Precedence) {
+        if self.depth == MAX_PARSE_DEPTH {
+            // The rest of the script is skipped over, as the parser is in panic mode, but without
+            // recursing any deeper.
+            self.error_at_current("Expression nests too deeply.");
+            return;
+        }
+        self.depth += 1;
+        self.parse_precedence_at_depth(precedence);
+        self.depth -= 1;
+    }
+
+    fn parse_precedence_at_depth(&mut self, precedence: Precedence) {
         self.advance();
         let prefix_rule = self.get_rule(self.parser.previous.kind).prefix;
         match prefix_rule {
@@ -427,8 +497,280 @@ impl<'a> Compiler<'a> {
             TokenKind::True => ParseRule::of_prefix(Compiler::literal, Precedence::None),
             TokenKind::Var => ParseRule::none(),
             TokenKind::While => ParseRule::none(),
+            TokenKind::DocComment => ParseRule::none(),
+            TokenKind::Whitespace => ParseRule::none(),
+            TokenKind::LineComment => ParseRule::none(),
+            TokenKind::BlockComment => ParseRule::none(),
             TokenKind::Error => ParseRule::none(),
             TokenKind::Eof => ParseRule::none(),
         }
     }
 }
+
+// Parses the lexeme of a TokenKind::Number token into its value.
+//
+// The scanner is deliberately lenient about what it considers part of a number literal, so this is
+// where malformed literals (like `0b102`, `1e` or `1__0`) and literals that are too large to be
+// represented (like `1e999`) are rejected.
+fn parse_number(lexeme: &str) -> Result<f64, &'static str> {
+    let (radix, digits) = match lexeme.get(..2) {
+        Some("0x" | "0X") => (16, &lexeme[2..]),
+        Some("0b" | "0B") => (2, &lexeme[2..]),
+        _ => (10, lexeme),
+    };
+
+    if radix != 10 {
+        if !is_valid_digit_sequence(digits, radix) {
+            return Err(if radix == 16 {
+                "Invalid hexadecimal number literal."
+            } else {
+                "Invalid binary number literal."
+            });
+        }
+        // Like in JavaScript, integers above 2^53 are rounded to the nearest f64, and literals
+        // have the same range as decimal ones.
+        let number = parse_power_of_two_radix(digits, radix);
+        return if number.is_finite() {
+            Ok(number)
+        } else {
+            Err("Number literal out of range.")
+        };
+    }
+
+    // A decimal literal has the form: digits ( "." digits )? ( ( "e" | "E" ) ( "+" | "-" )? digits )?
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(index) => (&digits[..index], Some(&digits[index + 1..])),
+        None => (digits, None),
+    };
+    let (integer_part, fractional_part) = match mantissa.split_once('.') {
+        Some((integer_part, fractional_part)) => (integer_part, Some(fractional_part)),
+        None => (mantissa, None),
+    };
+    let exponent = exponent.map(|exponent| exponent.strip_prefix(['+', '-']).unwrap_or(exponent));
+    if !is_valid_digit_sequence(integer_part, 10)
+        || !fractional_part.is_none_or(|part| is_valid_digit_sequence(part, 10))
+        || !exponent.is_none_or(|part| is_valid_digit_sequence(part, 10))
+    {
+        return Err("Invalid number literal.");
+    }
+
+    match digits.replace('_', "").parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err("Number literal out of range."),
+    }
+}
+
+// Converts a valid sequence of hexadecimal or binary digits to the nearest f64, which is infinite if
+// it's too large.
+//
+// The digits are accumulated into a u128 until it's nearly full. Each digit after that only
+// doubles the number some more times, but if it isn't zero it's remembered in the lowest bit, so
+// that the number still rounds the right way when converted to an f64. Scaling by a power of two
+// is exact, so the result is correctly rounded.
+fn parse_power_of_two_radix(digits: &str, radix: u32) -> f64 {
+    let bits_per_digit = radix.trailing_zeros();
+    let mut significand = 0u128;
+    let mut exponent = 0i32;
+    let mut sticky = false;
+    for digit in digits.chars().filter_map(|c| c.to_digit(radix)) {
+        if significand.leading_zeros() >= bits_per_digit {
+            significand = (significand << bits_per_digit) | digit as u128;
+        } else {
+            exponent = exponent.saturating_add(bits_per_digit as i32);
+            sticky |= digit != 0;
+        }
+    }
+    (significand | sticky as u128) as f64 * 2f64.powi(exponent)
+}
+
+// Returns true if the given string is a non-empty sequence of digits in the given radix, where
+// every `_` digit separator sits between two digits.
+fn is_valid_digit_sequence(digits: &str, radix: u32) -> bool {
+    let bytes = digits.as_bytes();
+    !bytes.is_empty()
+        && bytes.iter().enumerate().all(|(index, &byte)| {
+            if byte == b'_' {
+                index > 0
+                    && index < bytes.len() - 1
+                    && (bytes[index - 1] as char).is_digit(radix)
+                    && (bytes[index + 1] as char).is_digit(radix)
+            } else {
+                (byte as char).is_digit(radix)
+            }
+        })
+}
+
+// Evaluates a binary operator on constant operands at compile time, giving the same result as the
+// VM would. Returns None if the VM would report a runtime error instead.
+//
+// Strings aren't concatenated, because OP_ADD only works on numbers so far.
+fn fold_binary(operator_kind: TokenKind, a: &Value, b: &Value) -> Option<Value> {
+    match operator_kind {
+        TokenKind::EqualEqual => return Some(Value::bool(a == b)),
+        TokenKind::BangEqual => return Some(Value::bool(a != b)),
+        _ => {}
+    }
+
+    let (Some(a), Some(b)) = (a.as_number(), b.as_number()) else {
+        return None;
+    };
+    Some(match operator_kind {
+        TokenKind::Plus => Value::number(a + b),
+        TokenKind::Minus => Value::number(a - b),
+        TokenKind::Star => Value::number(a * b),
+        TokenKind::Slash => Value::number(a / b),
+        TokenKind::Greater => Value::bool(a > b),
+        TokenKind::Less => Value::bool(a < b),
+        TokenKind::GreaterEqual => Value::bool(a >= b),
+        TokenKind::LessEqual => Value::bool(a <= b),
+        _ => unreachable!(),
+    })
+}
+
+// Evaluates a unary operator on a constant operand at compile time, or returns None if the VM
+// would report a runtime error instead.
+fn fold_unary(operator_kind: TokenKind, value: &Value) -> Option<Value> {
+    match (operator_kind, value) {
+        (TokenKind::Bang, _) => Some(Value::bool(value.is_falsey())),
+        (TokenKind::Minus, _) => value.as_number().map(|number| Value::number(-number)),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compiles the given source without optimizing it, and returns the chunk.
+    fn compile_unoptimized(source: &str) -> Chunk {
+        let mut chunk = Chunk::new();
+        assert!(Compiler::new(source, &mut chunk).compile());
+        chunk
+    }
+
+    #[test]
+    fn keeps_the_line_table_in_step_with_folded_code() {
+        // The folded 1 + 2 replaces code that started partway through line 1's run.
+        let chunk = compile_unoptimized("\"a\" + (1\n+\n2)");
+        assert_eq!(
+            chunk.instruction_lines(),
+            [
+                (OpCode::Constant, vec![0], 1),
+                (OpCode::Constant, vec![1], 3),
+                (OpCode::Add, vec![], 3),
+                (OpCode::Return, vec![], 3),
+            ]
+        );
+        assert_eq!(
+            chunk.constants,
+            [Value::string("a".to_string()), Value::number(3.0)]
+        );
+
+        let chunk = compile_unoptimized("-(1 +\n2) ==\n\n-3");
+        assert_eq!(
+            chunk.instruction_lines(),
+            [(OpCode::True, vec![], 4), (OpCode::Return, vec![], 4)]
+        );
+        assert!(chunk.constants.is_empty());
+    }
+
+    #[test]
+    fn folds_comparisons_with_nan_like_the_vm() {
+        let cases = [
+            ("0/0 == 0/0", OpCode::False),
+            ("0/0 != 0/0", OpCode::True),
+            ("0/0 <= 1", OpCode::False),
+            ("0/0 >= 1", OpCode::False),
+            ("0/0 != 1", OpCode::True),
+            ("1 <= 0/0", OpCode::False),
+            ("1 >= 0/0", OpCode::False),
+        ];
+        for (source, op_code) in cases {
+            assert_eq!(
+                compile_unoptimized(source).instruction_lines(),
+                [(op_code, vec![], 1), (OpCode::Return, vec![], 1)],
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_expressions_that_nest_too_deeply() {
+        // Every level of -( recurses through parse_precedence() twice.
+        let nested = |depth: usize| format!("{}1{}", "-(".repeat(depth), ")".repeat(depth));
+        assert!(compile(&nested(MAX_PARSE_DEPTH / 2 - 1)).is_some());
+        assert!(compile(&nested(MAX_PARSE_DEPTH / 2)).is_none());
+        assert!(compile(&nested(200_000)).is_none());
+    }
+
+    #[test]
+    fn parses_decimal_literals() {
+        assert_eq!(parse_number("0"), Ok(0.0));
+        assert_eq!(parse_number("123"), Ok(123.0));
+        assert_eq!(parse_number("1.5"), Ok(1.5));
+        assert_eq!(parse_number("1_000_000"), Ok(1_000_000.0));
+        assert_eq!(parse_number("1_0.2_5"), Ok(10.25));
+        assert_eq!(parse_number("1e3"), Ok(1000.0));
+        assert_eq!(parse_number("1E+3"), Ok(1000.0));
+        assert_eq!(parse_number("2.5e-3"), Ok(0.0025));
+        assert_eq!(parse_number("1e1_0"), Ok(1e10));
+        assert_eq!(parse_number("1.7976931348623157e308"), Ok(f64::MAX));
+    }
+
+    #[test]
+    fn parses_hexadecimal_and_binary_literals() {
+        assert_eq!(parse_number("0x1F"), Ok(31.0));
+        assert_eq!(parse_number("0Xff"), Ok(255.0));
+        assert_eq!(parse_number("0xFF_FF"), Ok(65535.0));
+        assert_eq!(parse_number("0b1010"), Ok(10.0));
+        assert_eq!(parse_number("0B1_0000"), Ok(16.0));
+        assert_eq!(parse_number("0x1_0000_0000_0000_0000"), Ok(2f64.powi(64)));
+        // Literals with more bits than an f64 are rounded to the nearest one, with ties going to
+        // the even one.
+        assert_eq!(parse_number("0x20000000000001"), Ok(2f64.powi(53)));
+        assert_eq!(parse_number("0x20000000000003"), Ok(2f64.powi(53) + 4.0));
+        // Digits past the first 128 bits still break ties.
+        assert_eq!(
+            parse_number(&format!("0x20000000000001{}1", "0".repeat(29))),
+            Ok(2f64.powi(173) + 2f64.powi(121))
+        );
+        assert_eq!(
+            parse_number(&format!("0x1{}", "0".repeat(255))),
+            Ok(2f64.powi(1020))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_literals() {
+        for (lexeme, message) in [
+            ("1e", "Invalid number literal."),
+            ("1e+", "Invalid number literal."),
+            ("1.5e-x", "Invalid number literal."),
+            ("12abc", "Invalid number literal."),
+            ("1__0", "Invalid number literal."),
+            ("1_", "Invalid number literal."),
+            ("1_.5", "Invalid number literal."),
+            ("0x", "Invalid hexadecimal number literal."),
+            ("0xG", "Invalid hexadecimal number literal."),
+            ("0x_1", "Invalid hexadecimal number literal."),
+            ("0x1.5", "Invalid hexadecimal number literal."),
+            ("0b", "Invalid binary number literal."),
+            ("0b102", "Invalid binary number literal."),
+        ] {
+            assert_eq!(parse_number(lexeme), Err(message), "{lexeme}");
+        }
+    }
+
+    #[test]
+    fn rejects_literals_that_are_out_of_range() {
+        assert_eq!(parse_number("1e309"), Err("Number literal out of range."));
+        assert_eq!(
+            parse_number(&format!("0x1{}", "0".repeat(256))),
+            Err("Number literal out of range.")
+        );
+        assert_eq!(
+            parse_number(&format!("0b1{}", "0".repeat(1024))),
+            Err("Number literal out of range.")
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::scanner::{Scanner, Token, TokenKind};
+
+/// Formats Lox source code in a canonical style, keeping all of its comments.
+///
+/// Returns the first error token if the source cannot be scanned.
+pub fn format(source: &str) -> Result<String, Token<'_>> {
+    let mut formatter = Formatter::new();
+    for token in Scanner::with_trivia(source) {
+        match token.kind {
+            TokenKind::Error => return Err(token),
+            TokenKind::Eof => break,
+            _ => formatter.write_token(&token),
+        }
+    }
+    Ok(formatter.finish())
+}
+
+const INDENT: &str = "    ";
+
+struct Formatter {
+    output: String,
+    indent: usize,
+    // The last token written that wasn't a comment, if any has been written on the current line.
+    previous: Option<TokenKind>,
+    // Whether `previous` was a prefix operator, like the - in -1.
+    previous_is_unary: bool,
+    // Line breaks are written lazily, so that a comment that trails a statement can stay on the
+    // same line as the statement.
+    pending_newline: bool,
+    pending_blank_line: bool,
+    // Whether the source had a line break just before the current token.
+    newline_in_source: bool,
+}
+
+impl Formatter {
+    fn new() -> Formatter {
+        Formatter {
+            output: String::new(),
+            indent: 0,
+            previous: None,
+            previous_is_unary: false,
+            pending_newline: false,
+            pending_blank_line: false,
+            newline_in_source: false,
+        }
+    }
+
+    fn write_token(&mut self, token: &Token) {
+        match token.kind {
+            TokenKind::Whitespace => {
+                let newlines = token.lexeme.matches('\n').count();
+                self.newline_in_source = newlines > 0;
+                // Keep at most one blank line, and only where a line break is written anyway.
+                if newlines > 1 && self.pending_newline {
+                    self.pending_blank_line = true;
+                }
+                return;
+            }
+            TokenKind::LineComment => {
+                if self.newline_in_source {
+                    self.break_line();
+                } else if self.pending_newline {
+                    // Keep the comment on the line that it trails.
+                    self.pending_newline = false;
+                }
+                self.start_token(true);
+                self.output.push_str(token.lexeme.trim_end());
+                // The comment may be in the middle of an expression, so keep track of the previous
+                // token.
+                self.break_line();
+            }
+            TokenKind::DocComment => {
+                self.end_line();
+                self.start_token(false);
+                self.output.push_str(token.lexeme.trim_end());
+                self.end_line();
+            }
+            TokenKind::BlockComment => {
+                self.start_token(true);
+                self.output.push_str(token.lexeme);
+            }
+            TokenKind::LeftBrace => {
+                self.start_token(true);
+                self.output.push('{');
+                self.indent += 1;
+                self.end_line();
+            }
+            TokenKind::RightBrace => {
+                self.indent = self.indent.saturating_sub(1);
+                self.end_line();
+                self.start_token(false);
+                self.output.push('}');
+                self.end_line();
+            }
+            TokenKind::Semicolon => {
+                self.start_token(false);
+                self.output.push(';');
+                self.end_line();
+            }
+            kind => {
+                let space = self.needs_space_before(kind);
+                self.start_token(space);
+                self.output.push_str(token.lexeme);
+                self.previous_is_unary = matches!(kind, TokenKind::Minus | TokenKind::Bang)
+                    && !self.previous.is_some_and(ends_operand);
+                self.previous = Some(kind);
+            }
+        }
+        self.newline_in_source = false;
+    }
+
+    fn needs_space_before(&self, kind: TokenKind) -> bool {
+        // Either the token starts a line, or it follows a block comment.
+        let Some(previous) = self.previous else {
+            return true;
+        };
+        if self.previous_is_unary {
+            // Keep - -1 from reading as --1.
+            return previous == TokenKind::Minus && kind == TokenKind::Minus;
+        }
+        match (previous, kind) {
+            (_, TokenKind::RightParen | TokenKind::Comma | TokenKind::Dot) => false,
+            (TokenKind::LeftParen | TokenKind::Dot, _) => false,
+            // A call, like f(x).
+            (TokenKind::Identifier | TokenKind::RightParen, TokenKind::LeftParen) => false,
+            _ => true,
+        }
+    }
+
+    // Writes any pending line break and the indentation of a new line, or a space if requested
+    // and the token continues the current line.
+    fn start_token(&mut self, space: bool) {
+        if self.pending_newline {
+            self.output.push('\n');
+            if self.pending_blank_line {
+                self.output.push('\n');
+            }
+            self.pending_newline = false;
+            self.pending_blank_line = false;
+        }
+
+        if self.output.is_empty() || self.output.ends_with('\n') {
+            for _ in 0..self.indent {
+                self.output.push_str(INDENT);
+            }
+        } else if space {
+            self.output.push(' ');
+        }
+    }
+
+    fn break_line(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.pending_newline = true;
+        }
+    }
+
+    fn end_line(&mut self) {
+        self.break_line();
+        self.previous = None;
+        self.previous_is_unary = false;
+    }
+
+    fn finish(mut self) -> String {
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+        self.output
+    }
+}
+
+// Returns true if a token of the given kind can end an operand, in which case a following - is a
+// binary operator rather than a unary one.
+fn ends_operand(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Number
+            | TokenKind::String
+            | TokenKind::Identifier
+            | TokenKind::RightParen
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::Nil
+            | TokenKind::This
+            | TokenKind::Super
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Formats the given source, checking that formatting the result doesn't change it.
+    fn format_twice(source: &str) -> String {
+        let formatted = format(source).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted, "{source:?}");
+        formatted
+    }
+
+    #[test]
+    fn formats_idempotently() {
+        let sources = [
+            "fun f(a,b){return a*b;}\n/// Doc.\nprint f( 1 , 2 );",
+            "{print 1;\n\n\n print 2;}",
+            "var a = \"x\"; print a; // Trailing.\n// Leading.\nprint -a;",
+            "print !(1 <= 2) == !!nil;",
+        ];
+        for source in sources {
+            format_twice(source);
+        }
+        for entry in std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/test")).unwrap() {
+            for script in std::fs::read_dir(entry.unwrap().path()).unwrap() {
+                format_twice(&std::fs::read_to_string(script.unwrap().path()).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_trailing_comments_on_their_line() {
+        assert_eq!(
+            format_twice("print 1;   // One.\nprint 2;"),
+            "print 1; // One.\nprint 2;\n"
+        );
+        assert_eq!(
+            format_twice("{ print 1; // One.\n}"),
+            "{\n    print 1; // One.\n}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_in_expressions() {
+        assert_eq!(
+            format_twice("print 1 + // Why.\n 2;"),
+            "print 1 + // Why.\n2;\n"
+        );
+        assert_eq!(
+            format_twice("print 1 +/* Why. */2;"),
+            "print 1 + /* Why. */ 2;\n"
+        );
+    }
+
+    #[test]
+    fn spaces_binary_but_not_unary_minus() {
+        assert_eq!(format_twice("1 - -1"), "1 - -1\n");
+        assert_eq!(format_twice("1--1"), "1 - -1\n");
+        assert_eq!(format_twice("- -1"), "- -1\n");
+        assert_eq!(format_twice("-(-1)"), "-(-1)\n");
+        assert_eq!(format_twice("! -1"), "!-1\n");
+    }
+
+    #[test]
+    fn keeps_nested_block_comments() {
+        assert_eq!(
+            format_twice("print /* a /* b */ c */ 1;"),
+            "print /* a /* b */ c */ 1;\n"
+        );
+    }
+
+    #[test]
+    fn returns_the_first_error_token() {
+        let error = format("print 1;\nprint 1 @ 2; \"a").unwrap_err();
+        assert_eq!(error.kind, TokenKind::Error);
+        assert_eq!(error.lexeme, "Unexpected character.");
+        assert_eq!(error.line, 2);
+
+        let error = format("/* Unterminated.").unwrap_err();
+        assert_eq!(error.lexeme, "Unterminated block comment.");
+    }
+}
@@ -1,5 +1,9 @@
 pub mod chunk;
 pub mod compiler;
+pub mod formatter;
+pub mod loxc;
+pub mod optimizer;
 pub mod scanner;
 pub mod value;
+pub mod verifier;
 pub mod vm;
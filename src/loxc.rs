@@ -0,0 +1,318 @@
+// Reads and writes compiled chunks in the .loxc format, so that scripts can be shipped and run
+// without being compiled again.
+//
+// All integers are little-endian. A .loxc file consists of:
+//
+// - The magic bytes "LOXC".
+// - The format version, as a u16.
+// - The length of the code as a u32, followed by the code itself.
+// - The line table, run-length encoded: the number of runs as a u32, followed by each run as a
+//   u32 line and the u32 number of consecutive code bytes on that line.
+// - The number of constants as a u32, followed by each constant as a tag byte and its payload:
+//   nil, false and true have no payload, numbers are the 8 bytes of an f64 and strings are their
+//   length in bytes as a u32, followed by their UTF-8 bytes.
+
+use crate::chunk::Chunk;
+use crate::value::Value;
+use std::fmt::{Display, Formatter};
+use std::{error, fmt, io};
+
+const MAGIC: &[u8; 4] = b"LOXC";
+const VERSION: u16 = 1;
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+
+/// Returns true if the given bytes start like a .loxc file, rather than like Lox source code.
+pub fn is_loxc(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Writes the given chunk in the .loxc format.
+pub fn write(chunk: &Chunk, out: &mut dyn io::Write) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+    write_length(&mut bytes, chunk.code.len())?;
+    bytes.extend_from_slice(&chunk.code);
+
+    let runs = chunk.line_runs();
+    write_length(&mut bytes, runs.len())?;
+    let mut start = 0;
+    for run in runs {
+        write_length(&mut bytes, run.line)?;
+        write_length(&mut bytes, run.end - start)?;
+        start = run.end;
+    }
+
+    write_length(&mut bytes, chunk.constants.len())?;
+    for constant in &chunk.constants {
+        if let Some(number) = constant.as_number() {
+            bytes.push(TAG_NUMBER);
+            bytes.extend_from_slice(&number.to_le_bytes());
+        } else if let Some(string) = constant.as_str() {
+            bytes.push(TAG_STRING);
+            write_length(&mut bytes, string.len())?;
+            bytes.extend_from_slice(string.as_bytes());
+        } else {
+            bytes.push(match constant.as_bool() {
+                Some(false) => TAG_FALSE,
+                Some(true) => TAG_TRUE,
+                None => TAG_NIL,
+            });
+        }
+    }
+
+    out.write_all(&bytes)
+}
+
+fn write_length(bytes: &mut Vec<u8>, length: usize) -> io::Result<()> {
+    let length = u32::try_from(length).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Chunk is too large for the .loxc format.",
+        )
+    })?;
+    bytes.extend_from_slice(&length.to_le_bytes());
+    Ok(())
+}
+
+/// Reads a chunk in the .loxc format.
+///
+/// Note that this only checks that the file is well-formed, not that its bytecode is.
+pub fn read(bytes: &[u8]) -> Result<Chunk, LoadError> {
+    let mut reader = Reader { bytes, position: 0 };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(LoadError::NotLoxc);
+    }
+    let version = u16::from_le_bytes([reader.u8()?, reader.u8()?]);
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let mut chunk = Chunk::new();
+
+    let code_length = reader.length()?;
+    chunk.code = reader.take(code_length)?.to_vec();
+
+    let run_count = reader.length()?;
+    for _ in 0..run_count {
+        let line = reader.length()?;
+        let count = reader.length()?;
+        if chunk.line_table_len() + count > chunk.code.len() {
+            return Err(LoadError::InvalidLineTable);
+        }
+        chunk.push_line_run(line, count);
+    }
+    if chunk.line_table_len() != chunk.code.len() {
+        return Err(LoadError::InvalidLineTable);
+    }
+
+    let constant_count = reader.length()?;
+    for _ in 0..constant_count {
+        let constant = match reader.u8()? {
+            TAG_NIL => Value::nil(),
+            TAG_FALSE => Value::bool(false),
+            TAG_TRUE => Value::bool(true),
+            TAG_NUMBER => {
+                let number = reader.take(8)?;
+                Value::number(f64::from_le_bytes(number.try_into().unwrap()))
+            }
+            TAG_STRING => {
+                let length = reader.length()?;
+                let string = std::str::from_utf8(reader.take(length)?)
+                    .map_err(|_| LoadError::InvalidString)?;
+                Value::string(string.to_string())
+            }
+            tag => return Err(LoadError::InvalidConstantTag(tag)),
+        };
+        chunk.constants.push(constant);
+    }
+
+    if reader.position != bytes.len() {
+        return Err(LoadError::TrailingBytes);
+    }
+
+    Ok(chunk)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(LoadError::Truncated)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<usize, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+}
+
+/// The reasons that a .loxc file can fail to load.
+#[derive(Copy, Clone, Debug, PartialEq, Hash)]
+pub enum LoadError {
+    NotLoxc,
+    UnsupportedVersion(u16),
+    Truncated,
+    TrailingBytes,
+    InvalidLineTable,
+    InvalidConstantTag(u8),
+    InvalidString,
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotLoxc => write!(f, "Not a .loxc file."),
+            LoadError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "Unsupported .loxc version {version}, expected {VERSION}."
+                )
+            }
+            LoadError::Truncated => write!(f, "Unexpected end of .loxc file."),
+            LoadError::TrailingBytes => write!(f, "Unexpected bytes after the end of the chunk."),
+            LoadError::InvalidLineTable => {
+                write!(f, "Line table doesn't match the length of the code.")
+            }
+            LoadError::InvalidConstantTag(tag) => write!(f, "Invalid constant tag {tag}."),
+            LoadError::InvalidString => write!(f, "String constant isn't valid UTF-8."),
+        }
+    }
+}
+
+impl error::Error for LoadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::OpCode;
+
+    // Returns a .loxc file with the given code, line runs of (line, byte count) and constants,
+    // which must be encoded already.
+    fn file(code: &[u8], runs: &[(u32, u32)], constant_count: u32, constants: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(code.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(code);
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (line, count) in runs {
+            bytes.extend_from_slice(&line.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+        }
+        bytes.extend_from_slice(&constant_count.to_le_bytes());
+        bytes.extend_from_slice(constants);
+        bytes
+    }
+
+    fn written(chunk: &Chunk) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(chunk, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let mut chunk = Chunk::new();
+        chunk.write_constant(Value::number(1.5), 1);
+        chunk.write_constant(Value::string("héllo".to_string()), 2);
+        chunk.write_op_code(OpCode::Add, 2);
+        chunk.write_op_code(OpCode::Negate, 4);
+        chunk.write_op_code(OpCode::Return, 4);
+        chunk.constants.push(Value::nil());
+        chunk.constants.push(Value::bool(false));
+        chunk.constants.push(Value::bool(true));
+
+        let bytes = written(&chunk);
+        assert!(is_loxc(&bytes));
+        let read = read(&bytes).unwrap();
+
+        assert_eq!(read.code, chunk.code);
+        assert_eq!(read.line_runs(), chunk.line_runs());
+        assert_eq!(read.constants, chunk.constants);
+    }
+
+    #[test]
+    fn tells_loxc_files_from_source_code() {
+        assert!(!is_loxc(b"print \"LOXC\";"));
+        assert!(!is_loxc(b"LOX"));
+    }
+
+    #[test]
+    fn rejects_files_without_the_magic_bytes() {
+        assert_eq!(read(b"LOXX\x01\x00").err(), Some(LoadError::NotLoxc));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = written(&Chunk::new());
+        bytes[4..6].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(read(&bytes).err(), Some(LoadError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mut chunk = Chunk::new();
+        chunk.write_constant(Value::string("a".to_string()), 1);
+        chunk.write_constant(Value::number(2.0), 2);
+        let bytes = written(&chunk);
+        for length in 0..bytes.len() {
+            assert_eq!(
+                read(&bytes[..length]).err(),
+                Some(LoadError::Truncated),
+                "truncated to {length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut bytes = written(&Chunk::new());
+        bytes.push(0);
+        assert_eq!(read(&bytes).err(), Some(LoadError::TrailingBytes));
+    }
+
+    #[test]
+    fn rejects_line_tables_that_dont_match_the_code() {
+        let code = [OpCode::Nil as u8, OpCode::Return as u8];
+        // Too many lines.
+        let bytes = file(&code, &[(1, 2), (2, 1)], 0, &[]);
+        assert_eq!(read(&bytes).err(), Some(LoadError::InvalidLineTable));
+        // Too few lines.
+        let bytes = file(&code, &[(1, 1)], 0, &[]);
+        assert_eq!(read(&bytes).err(), Some(LoadError::InvalidLineTable));
+    }
+
+    #[test]
+    fn rejects_unknown_constant_tags() {
+        let bytes = file(&[], &[], 1, &[9]);
+        assert_eq!(read(&bytes).err(), Some(LoadError::InvalidConstantTag(9)));
+    }
+
+    #[test]
+    fn rejects_strings_that_arent_utf8() {
+        let bytes = file(&[], &[], 1, &[TAG_STRING, 2, 0, 0, 0, 0xc3, 0x28]);
+        assert_eq!(read(&bytes).err(), Some(LoadError::InvalidString));
+    }
+}
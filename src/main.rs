@@ -1,32 +1,346 @@
-use rust_clox::vm::{InterpretError, VM};
-use std::env;
-use std::fs::read_to_string;
-use std::io::{self, BufRead, Stdout, Write};
-use std::process::exit;
+use rust_clox::chunk::Chunk;
+use rust_clox::compiler::compile;
+use rust_clox::formatter::format;
+use rust_clox::loxc;
+use rust_clox::scanner::{Scanner, Token, TokenKind};
+use rust_clox::verifier::verify;
+use rust_clox::vm::{Config, InterpretError, VM};
+use std::fs::{read_dir, read_to_string, File};
+use std::io::{self, BufRead, Read, Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command as Process};
+use std::{env, fs};
+
+const USAGE: &str = "\
+Usage: rust-clox [command] [options] [path | - | -e <code>]
+
+Commands:
+  run      Run a script, or a .loxc file. This is the default if a script is given.
+  repl     Start an interactive session. This is the default if no script is given.
+  compile  Compile a script to bytecode, and write it to a .loxc file.
+  disasm   Print the bytecode that a script, or a .loxc file, consists of.
+  tokens   Print the tokens that a script is made of.
+  check    Report compile errors in scripts, or invalid bytecode in .loxc files, without
+           running them.
+  fmt      Print a script in the canonical format.
+  test     Run scripts, or directories of scripts, and compare their output with their
+           `// expect: <output>`, `// expect runtime error: <message>` and
+           `// Error <message>` comments.
+
+Options:
+  -e <code>        Use <code> as the script rather than reading it from a file.
+  -                Read the script from stdin.
+  --trace          Print the stack and each instruction while running.
+  --print-code     Print the bytecode of each script before running it.
+  --max-steps <n>  Stop after running <n> instructions, reporting that the script exceeded
+                   the maximum number of steps.
+  --max-stack <n>  Stop with a runtime error when the stack would hold more than <n> values.
+  --max-heap <n>   Stop with a runtime error when running would allocate more than <n> bytes.
+  -o <path>        For compile: the .loxc file to write. By default, this is the path of the
+                   script with its extension replaced by .loxc.
+  --json           For tokens: print the tokens as JSON.
+  --check          For fmt: rather than printing the script, fail if it isn't formatted.
+  -h, --help       Print this message.
+";
 
 fn main() {
     exit(run_app());
 }
 
 fn run_app() -> i32 {
-    let args = env::args().collect::<Vec<String>>();
-    match args.len() {
-        1 => repl().map_or_else(
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match parse_args(&args) {
+        Ok(Some(options)) => run_command(&options),
+        Ok(None) => {
+            print!("{USAGE}");
+            0
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            eprint!("{USAGE}");
+            64
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Command {
+    Run,
+    Repl,
+    Compile,
+    Disasm,
+    Tokens,
+    Check,
+    Fmt,
+    Test,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "run" => Some(Command::Run),
+            "repl" => Some(Command::Repl),
+            "compile" => Some(Command::Compile),
+            "disasm" => Some(Command::Disasm),
+            "tokens" => Some(Command::Tokens),
+            "check" => Some(Command::Check),
+            "fmt" => Some(Command::Fmt),
+            "test" => Some(Command::Test),
+            _ => None,
+        }
+    }
+}
+
+enum Input {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+impl Input {
+    fn name(&self) -> &str {
+        match self {
+            Input::File(path) => path,
+            Input::Stdin => "<stdin>",
+            Input::Inline(_) => "<inline>",
+        }
+    }
+
+    fn read(&self) -> io::Result<String> {
+        match self {
+            Input::File(path) => read_to_string(path),
+            Input::Stdin => {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source)?;
+                Ok(source)
+            }
+            Input::Inline(code) => Ok(code.clone()),
+        }
+    }
+
+    fn read_bytes(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Input::File(path) => fs::read(path),
+            Input::Stdin => {
+                let mut bytes = Vec::new();
+                io::stdin().read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            Input::Inline(code) => Ok(code.as_bytes().to_vec()),
+        }
+    }
+}
+
+// A script to run or disassemble, which is either Lox source code or bytecode that was compiled
+// ahead of time into a .loxc file.
+enum Program {
+    Source(String),
+    Bytecode(Chunk),
+}
+
+struct Options {
+    command: Command,
+    inputs: Vec<Input>,
+    trace: bool,
+    print_code: bool,
+    max_steps: Option<usize>,
+    max_stack: Option<usize>,
+    max_heap: Option<usize>,
+    output: Option<String>,
+    json: bool,
+    check: bool,
+}
+
+// Returns None if help was requested.
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let (explicit_command, rest) = match args.first().and_then(|arg| Command::from_name(arg)) {
+        Some(command) => (Some(command), &args[1..]),
+        None => (None, args),
+    };
+
+    let mut options = Options {
+        command: explicit_command.unwrap_or(Command::Run),
+        inputs: Vec::new(),
+        trace: false,
+        print_code: false,
+        max_steps: None,
+        max_stack: None,
+        max_heap: None,
+        output: None,
+        json: false,
+        check: false,
+    };
+
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-" => options.inputs.push(Input::Stdin),
+            "-e" => match rest.next() {
+                Some(code) => options.inputs.push(Input::Inline(code.clone())),
+                None => return Err("Expected code after -e.".to_string()),
+            },
+            "--trace" => options.trace = true,
+            "--print-code" => options.print_code = true,
+            "--max-steps" => match rest.next().map(|steps| steps.parse::<usize>()) {
+                Some(Ok(steps)) => options.max_steps = Some(steps),
+                _ => return Err("Expected a number of steps after --max-steps.".to_string()),
+            },
+            "--max-stack" => match rest.next().map(|size| size.parse::<usize>()) {
+                Some(Ok(size)) => options.max_stack = Some(size),
+                _ => return Err("Expected a number of values after --max-stack.".to_string()),
+            },
+            "--max-heap" => match rest.next().map(|size| size.parse::<usize>()) {
+                Some(Ok(size)) => options.max_heap = Some(size),
+                _ => return Err("Expected a number of bytes after --max-heap.".to_string()),
+            },
+            "-o" => match rest.next() {
+                Some(path) => options.output = Some(path.clone()),
+                None => return Err("Expected a path after -o.".to_string()),
+            },
+            "--json" => options.json = true,
+            "--check" => options.check = true,
+            option if option.starts_with('-') => {
+                return Err(format!("Unknown option '{option}'."));
+            }
+            path => options.inputs.push(Input::File(path.to_string())),
+        }
+    }
+
+    if explicit_command.is_none() && options.inputs.is_empty() {
+        options.command = Command::Repl;
+    }
+
+    validate_options(&options)?;
+    Ok(Some(options))
+}
+
+fn validate_options(options: &Options) -> Result<(), String> {
+    let command = options.command;
+    match command {
+        Command::Repl if !options.inputs.is_empty() => {
+            return Err("The repl command doesn't take a script.".to_string());
+        }
+        Command::Run | Command::Compile | Command::Disasm | Command::Tokens | Command::Fmt
+            if options.inputs.len() != 1 =>
+        {
+            return Err("Expected exactly one script.".to_string());
+        }
+        Command::Check | Command::Test if options.inputs.is_empty() => {
+            return Err("Expected at least one script.".to_string());
+        }
+        Command::Test
+            if options
+                .inputs
+                .iter()
+                .any(|input| !matches!(input, Input::File(_))) =>
+        {
+            return Err("The test command only takes paths.".to_string());
+        }
+        _ => {}
+    }
+
+    let runs_scripts = matches!(command, Command::Run | Command::Repl);
+    if !runs_scripts
+        && (options.trace
+            || options.print_code
+            || options.max_steps.is_some()
+            || options.max_stack.is_some()
+            || options.max_heap.is_some())
+    {
+        return Err(
+            "--trace, --print-code, --max-steps, --max-stack and --max-heap are only valid when \
+             running scripts."
+                .to_string(),
+        );
+    }
+    if options.output.is_some() && command != Command::Compile {
+        return Err("-o is only valid for the compile command.".to_string());
+    }
+    if options.json && command != Command::Tokens {
+        return Err("--json is only valid for the tokens command.".to_string());
+    }
+    if options.check && command != Command::Fmt {
+        return Err("--check is only valid for the fmt command.".to_string());
+    }
+
+    Ok(())
+}
+
+fn run_command(options: &Options) -> i32 {
+    match options.command {
+        Command::Repl => repl(options).map_or_else(
             |error| {
                 eprintln!("{error}");
                 70
             },
             |_| 0,
         ),
-        2 => run_file(&args[1]),
-        _ => {
-            eprintln!("Usage: rust-clox [path]");
-            64
+        Command::Run => with_program(&options.inputs[0], |program| run(options, program)),
+        Command::Compile => with_source(&options.inputs[0], |source| {
+            compile_to_file(options, source)
+        }),
+        Command::Disasm => with_program(&options.inputs[0], |program| {
+            disassemble(&options.inputs[0], program)
+        }),
+        Command::Tokens => with_source(&options.inputs[0], |source| {
+            dump_tokens(
+                source,
+                if options.json {
+                    TokenFormat::Json
+                } else {
+                    TokenFormat::Text
+                },
+            )
+        }),
+        Command::Check => check(&options.inputs),
+        Command::Fmt => with_source(&options.inputs[0], |source| {
+            format_source(&options.inputs[0], source, options.check)
+        }),
+        Command::Test => run_tests(&options.inputs),
+    }
+}
+
+fn with_source(input: &Input, f: impl FnOnce(&str) -> i32) -> i32 {
+    input.read().map_or_else(
+        |error| {
+            eprintln!("{}: {error}", input.name());
+            74
+        },
+        |source| f(&source),
+    )
+}
+
+// Like with_source(), but also accepts .loxc files.
+fn with_program(input: &Input, f: impl FnOnce(Program) -> i32) -> i32 {
+    let bytes = match input.read_bytes() {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("{}: {error}", input.name());
+            return 74;
+        }
+    };
+
+    if loxc::is_loxc(&bytes) {
+        return match loxc::read(&bytes) {
+            Ok(chunk) => f(Program::Bytecode(chunk)),
+            Err(error) => {
+                eprintln!("{}: {error}", input.name());
+                65
+            }
+        };
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(source) => f(Program::Source(source)),
+        Err(_) => {
+            eprintln!("{}: stream did not contain valid UTF-8", input.name());
+            74
         }
     }
 }
 
-fn repl() -> io::Result<()> {
+fn repl(options: &Options) -> io::Result<()> {
     // A real-world REPL should be able to handle multiple lines gracefully.
     // TODO: Address this comment.
     let stdin = io::stdin();
@@ -37,7 +351,7 @@ fn repl() -> io::Result<()> {
 
         match lines.next() {
             Some(line) => {
-                let _ = VM::new(&line?).interpret();
+                run(options, Program::Source(line?));
             }
             None => {
                 println!();
@@ -52,16 +366,370 @@ fn print(mut stdout: &Stdout, text: &str) -> io::Result<()> {
     stdout.flush()
 }
 
-fn run_file(path: &str) -> i32 {
-    read_to_string(path).map_or_else(
-        |error| {
-            eprintln!("{error}");
-            74
+fn run(options: &Options, program: Program) -> i32 {
+    let defaults = Config::default();
+    let config = Config {
+        print_code: if options.print_code {
+            Some(Box::new(io::stdout()))
+        } else {
+            defaults.print_code
         },
-        |source| match VM::new(&source).interpret() {
-            Ok(()) => 0,
-            Err(InterpretError::InterpretCompileError) => 65,
-            Err(InterpretError::InterpretRuntimeError) => 70,
+        trace_execution: if options.trace {
+            Some(Box::new(io::stdout()))
+        } else {
+            defaults.trace_execution
         },
-    )
+        max_steps: options.max_steps,
+        time_limit: defaults.time_limit,
+        max_stack_size: options.max_stack.unwrap_or(defaults.max_stack_size),
+        max_heap_size: options.max_heap,
+    };
+    let result = match program {
+        Program::Source(source) => VM::with_config(&source, config).interpret(),
+        Program::Bytecode(chunk) => VM::with_config("", config).interpret_chunk(chunk),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(InterpretError::InterpretCompileError) => 65,
+        Err(InterpretError::InterpretRuntimeError | InterpretError::InterpretOutOfMemory) => 70,
+        Err(InterpretError::InterpretBudgetExceeded) => {
+            eprintln!("Exceeded the maximum number of steps.");
+            70
+        }
+    }
+}
+
+fn compile_to_file(options: &Options, source: &str) -> i32 {
+    let output = match (&options.output, &options.inputs[0]) {
+        (Some(output), _) => PathBuf::from(output),
+        (None, Input::File(path)) if !path.ends_with(".loxc") => {
+            Path::new(path).with_extension("loxc")
+        }
+        _ => {
+            eprintln!("Expected the path of the .loxc file to write, with -o.");
+            return 64;
+        }
+    };
+
+    let Some(chunk) = compile(source) else {
+        return 65;
+    };
+
+    match File::create(&output).and_then(|mut file| loxc::write(&chunk, &mut file)) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{}: {error}", output.display());
+            74
+        }
+    }
+}
+
+fn disassemble(input: &Input, program: Program) -> i32 {
+    let chunk = match program {
+        Program::Source(source) => match compile(&source) {
+            Some(chunk) => chunk,
+            None => return 65,
+        },
+        Program::Bytecode(chunk) => chunk,
+    };
+    match chunk.disassemble(input.name(), &mut io::stdout()) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{error}");
+            74
+        }
+    }
+}
+
+fn check(inputs: &[Input]) -> i32 {
+    let mut exit_code = 0;
+    for input in inputs {
+        let result = with_program(input, |program| match program {
+            Program::Source(source) => compile(&source).map_or(65, |_| 0),
+            Program::Bytecode(chunk) => match verify(&chunk) {
+                Ok(()) => 0,
+                Err(error) => {
+                    eprintln!("{}: Invalid bytecode: {error}", input.name());
+                    65
+                }
+            },
+        });
+        exit_code = exit_code.max(result);
+    }
+    exit_code
+}
+
+fn format_source(input: &Input, source: &str, check: bool) -> i32 {
+    match format(source) {
+        Ok(formatted) if check => {
+            if formatted == source {
+                0
+            } else {
+                eprintln!("{} is not formatted.", input.name());
+                1
+            }
+        }
+        Ok(formatted) => {
+            print!("{formatted}");
+            0
+        }
+        Err(token) => {
+            eprintln!("[line {}] Error: {}", token.line, token.lexeme);
+            65
+        }
+    }
+}
+
+enum TokenFormat {
+    Text,
+    Json,
+}
+
+// Prints every token scanned from the given source, like the scanner-driving code from chapter 16
+// of craftinginterpreters.com, which is handy when debugging how the grammar treats some code.
+fn dump_tokens(source: &str, format: TokenFormat) -> i32 {
+    let tokens = Scanner::new(source).collect::<Vec<Token>>();
+    match format {
+        TokenFormat::Text => print_tokens_as_text(&tokens),
+        TokenFormat::Json => print_tokens_as_json(&tokens),
+    }
+
+    if tokens.iter().any(|token| token.kind == TokenKind::Error) {
+        65
+    } else {
+        0
+    }
+}
+
+fn print_tokens_as_text(tokens: &[Token]) {
+    let mut line = 0;
+    for token in tokens {
+        if token.line != line {
+            print!("{:4} ", token.line);
+            line = token.line;
+        } else {
+            print!("   | ");
+        }
+        println!("{:4} {:?} '{}'", token.column, token.kind, token.lexeme);
+    }
+}
+
+fn print_tokens_as_json(tokens: &[Token]) {
+    print!("{}", tokens_to_json(tokens));
+}
+
+fn tokens_to_json(tokens: &[Token]) -> String {
+    let mut json = String::from("[\n");
+    for (index, token) in tokens.iter().enumerate() {
+        let separator = if index + 1 < tokens.len() { "," } else { "" };
+        json.push_str(&format!(
+            "  {{\"kind\": \"{:?}\", \"lexeme\": \"{}\", \"line\": {}, \"column\": {}}}{separator}\n",
+            token.kind,
+            escape_json(token.lexeme),
+            token.line,
+            token.column,
+        ));
+    }
+    json.push_str("]\n");
+    json
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Runs each test script in a separate process, like the test runner of craftinginterpreters.com,
+// so that its output and exit code can be compared with what its comments expect.
+fn run_tests(inputs: &[Input]) -> i32 {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if let Input::File(path) = input {
+            if let Err(error) = collect_test_paths(Path::new(path), &mut paths) {
+                eprintln!("{path}: {error}");
+                return 74;
+            }
+        }
+    }
+
+    let executable = match env::current_exe() {
+        Ok(executable) => executable,
+        Err(error) => {
+            eprintln!("{error}");
+            return 70;
+        }
+    };
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for path in &paths {
+        match run_test(&executable, path) {
+            Ok(()) => passed += 1,
+            Err(failures) => {
+                failed += 1;
+                println!("FAIL {}", path.display());
+                for failure in failures {
+                    println!("     {failure}");
+                }
+            }
+        }
+    }
+
+    println!("{passed} passed, {failed} failed.");
+    if failed == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+fn collect_test_paths(path: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !fs::metadata(path)?.is_dir() {
+        paths.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir()
+            || entry
+                .extension()
+                .is_some_and(|extension| extension == "lox")
+        {
+            collect_test_paths(&entry, paths)?;
+        }
+    }
+    Ok(())
+}
+
+struct Expectations {
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+    exit_code: i32,
+}
+
+fn parse_expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations {
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+        exit_code: 0,
+    };
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let Some((_, comment)) = line.split_once("// ") else {
+            continue;
+        };
+
+        if let Some(output) = comment.strip_prefix("expect: ") {
+            expectations.stdout.push(output.to_string());
+        } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+            expectations.stderr.push(message.to_string());
+            expectations
+                .stderr
+                .push(format!("[line {line_number}] in script"));
+            expectations.exit_code = 70;
+        } else if comment.starts_with("[line ") && comment.contains("] Error") {
+            expectations.stderr.push(comment.to_string());
+            expectations.exit_code = 65;
+        } else if comment.starts_with("Error") {
+            expectations
+                .stderr
+                .push(format!("[line {line_number}] {comment}"));
+            expectations.exit_code = 65;
+        }
+    }
+
+    expectations
+}
+
+fn run_test(executable: &Path, path: &Path) -> Result<(), Vec<String>> {
+    let source = read_to_string(path).map_err(|error| vec![error.to_string()])?;
+    let expectations = parse_expectations(&source);
+
+    let output = Process::new(executable)
+        .arg("run")
+        .arg(path)
+        .output()
+        .map_err(|error| vec![error.to_string()])?;
+
+    let mut failures = Vec::new();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    compare_lines("output", &expectations.stdout, &stdout, &mut failures);
+    compare_lines("error", &expectations.stderr, &stderr, &mut failures);
+    match output.status.code() {
+        Some(code) if code == expectations.exit_code => {}
+        code => failures.push(format!(
+            "Expected exit code {}, but got {}.",
+            expectations.exit_code,
+            code.map_or("none".to_string(), |code| code.to_string()),
+        )),
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+fn compare_lines(kind: &str, expected: &[String], actual: &str, failures: &mut Vec<String>) {
+    let actual = actual.lines().collect::<Vec<&str>>();
+    for (index, expected_line) in expected.iter().enumerate() {
+        match actual.get(index) {
+            Some(actual_line) if actual_line == expected_line => {}
+            Some(actual_line) => failures.push(format!(
+                "Expected {kind} '{expected_line}', but got '{actual_line}'."
+            )),
+            None => failures.push(format!("Missing expected {kind} '{expected_line}'.")),
+        }
+    }
+    for actual_line in actual.iter().skip(expected.len()) {
+        failures.push(format!("Unexpected {kind} '{actual_line}'."));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(escape_json("plain"), "plain");
+        assert_eq!(escape_json(r#""quoted""#), r#"\"quoted\""#);
+        assert_eq!(escape_json(r"a\b"), r"a\\b");
+        assert_eq!(escape_json("\n\r\t"), r"\n\r\t");
+        assert_eq!(escape_json("\0\u{1b}\u{7f}"), r"\u0000\u001b\u007f");
+        assert_eq!(escape_json("été 日本 🦀"), "été 日本 🦀");
+    }
+
+    #[test]
+    fn separates_json_tokens_with_commas() {
+        let tokens = Scanner::new(r#""a\" +"#).collect::<Vec<Token>>();
+        assert_eq!(
+            tokens_to_json(&tokens),
+            r#"[
+  {"kind": "String", "lexeme": "\"a\\\"", "line": 1, "column": 1},
+  {"kind": "Plus", "lexeme": "+", "line": 1, "column": 6},
+  {"kind": "Eof", "lexeme": "", "line": 1, "column": 7}
+]
+"#
+        );
+        assert_eq!(tokens_to_json(&[]), "[\n]\n");
+    }
 }
@@ -0,0 +1,154 @@
+// A peephole optimizer, which replaces common sequences of instructions in a compiled chunk with
+// superinstructions that do the same work in a single dispatch.
+//
+// The sequences that are fused so far are:
+//
+// - OP_EQUAL, OP_NOT into OP_NOT_EQUAL, and OP_NOT_EQUAL, OP_NOT into OP_EQUAL, as in !(a == b).
+//   The other comparisons can't be fused like this, as !(a < b) isn't a >= b when either operand
+//   is NaN.
+// - OP_CONSTANT, OP_ADD into OP_ADD_CONSTANT, as in a + 1.
+//
+// There are no jumps yet, so instructions can be fused and moved freely. Once there are, an
+// instruction that a jump lands on must not be fused with the one before it, and the offsets of
+// jumps will need to be adjusted for the bytes that are removed.
+
+use crate::chunk::{Chunk, Instruction, OpCode};
+
+/// Replaces sequences of instructions in the given chunk with equivalent superinstructions.
+///
+/// The chunk must be well-formed: either it came from the compiler or it passed verification.
+pub fn optimize(chunk: &mut Chunk) {
+    let mut optimized = Chunk::new();
+    // The opcode, operands and offset of the last instruction written to the optimized code, so
+    // that the result of a fusion can be fused again, like in !!(a == b).
+    let mut previous: Option<(OpCode, Vec<u8>, usize)> = None;
+
+    for instruction in chunk.instructions() {
+        let Some(op_code) = instruction.op_code else {
+            copy(&mut optimized, &instruction);
+            previous = None;
+            continue;
+        };
+
+        let fused = previous
+            .as_ref()
+            .and_then(|(previous_op_code, operands, offset)| {
+                let (fused_op_code, fused_operands) = fuse(*previous_op_code, operands, op_code)?;
+                Some((fused_op_code, fused_operands, *offset))
+            });
+        let (op_code, operands, offset) = match fused {
+            Some((op_code, operands, offset)) => {
+                optimized.truncate(offset);
+                (op_code, operands, offset)
+            }
+            None => (op_code, instruction.operands.to_vec(), optimized.code.len()),
+        };
+
+        // A superinstruction gets the line of the last instruction that it replaces, which is the
+        // one whose runtime errors it reports.
+        let line = line(&instruction);
+        optimized.write_op_code(op_code, line);
+        for &operand in &operands {
+            optimized.write_byte(operand, line);
+        }
+        previous = Some((op_code, operands, offset));
+    }
+
+    chunk.replace_code(optimized);
+}
+
+// Returns the superinstruction, and its operands, that replaces the given pair of instructions, if
+// there is one.
+fn fuse(first: OpCode, first_operands: &[u8], second: OpCode) -> Option<(OpCode, Vec<u8>)> {
+    match (first, second) {
+        (OpCode::Equal, OpCode::Not) => Some((OpCode::NotEqual, Vec::new())),
+        (OpCode::NotEqual, OpCode::Not) => Some((OpCode::Equal, Vec::new())),
+        (OpCode::Constant, OpCode::Add) => Some((OpCode::AddConstant, first_operands.to_vec())),
+        _ => None,
+    }
+}
+
+fn copy(chunk: &mut Chunk, instruction: &Instruction) {
+    let line = line(instruction);
+    chunk.write_byte(instruction.byte, line);
+    for &operand in instruction.operands {
+        chunk.write_byte(operand, line);
+    }
+}
+
+fn line(instruction: &Instruction) -> usize {
+    instruction
+        .line
+        .expect("a well-formed chunk has a line for every byte")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    // Returns a chunk with the given instructions, each on the given line.
+    fn chunk(instructions: &[(OpCode, &[u8], usize)]) -> Chunk {
+        let mut chunk = Chunk::new();
+        for &(op_code, operands, line) in instructions {
+            chunk.write_op_code(op_code, line);
+            for &operand in operands {
+                chunk.write_byte(operand, line);
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn fuses_instructions_and_keeps_their_lines() {
+        let mut chunk = chunk(&[
+            (OpCode::Constant, &[0], 1),
+            (OpCode::Constant, &[1], 2),
+            (OpCode::Add, &[], 3),
+            (OpCode::Constant, &[0], 3),
+            (OpCode::Equal, &[], 4),
+            (OpCode::Not, &[], 5),
+            (OpCode::Return, &[], 5),
+        ]);
+        chunk.constants.push(Value::string("a".to_string()));
+        chunk.constants.push(Value::number(1.0));
+
+        optimize(&mut chunk);
+
+        assert_eq!(
+            chunk.instruction_lines(),
+            [
+                (OpCode::Constant, vec![0], 1),
+                (OpCode::AddConstant, vec![1], 3),
+                (OpCode::Constant, vec![0], 3),
+                (OpCode::NotEqual, vec![], 5),
+                (OpCode::Return, vec![], 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn fuses_superinstructions_again() {
+        let mut chunk = chunk(&[
+            (OpCode::Nil, &[], 1),
+            (OpCode::Nil, &[], 1),
+            (OpCode::Equal, &[], 2),
+            (OpCode::Not, &[], 3),
+            (OpCode::Not, &[], 4),
+            (OpCode::Not, &[], 5),
+            (OpCode::Return, &[], 6),
+        ]);
+
+        optimize(&mut chunk);
+
+        assert_eq!(
+            chunk.instruction_lines(),
+            [
+                (OpCode::Nil, vec![], 1),
+                (OpCode::Nil, vec![], 1),
+                (OpCode::NotEqual, vec![], 5),
+                (OpCode::Return, vec![], 6),
+            ]
+        );
+    }
+}
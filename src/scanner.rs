@@ -1,23 +1,68 @@
-pub(crate) struct Scanner<'a> {
-    source: &'a [u8],
+use unicode_xid::UnicodeXID;
+
+/// The lexer used by the interpreter, exposed so that tooling such as editor plugins, syntax
+/// highlighters and linters can reuse the exact same lexing rules.
+///
+/// Tokens can be pulled one at a time with [`Scanner::scan_token`], or iterated over, in which
+/// case the final token is always [`TokenKind::Eof`]:
+///
+/// ```
+/// use rust_clox::scanner::{Scanner, TokenKind};
+///
+/// let kinds = Scanner::new("1 + 2").map(|token| token.kind).collect::<Vec<_>>();
+/// assert_eq!(
+///     kinds,
+///     [TokenKind::Number, TokenKind::Plus, TokenKind::Number, TokenKind::Eof]
+/// );
+/// ```
+//
+// Note: `start` and `current` are byte offsets into `source`, which always sit on a char boundary,
+// whereas columns are counted in chars.
+pub struct Scanner<'a> {
+    source: &'a str,
     start: usize,
     current: usize,
+    start_line: usize,
     line: usize,
+    start_column: usize,
+    column: usize,
+    emit_trivia: bool,
+    finished: bool,
 }
 
 impl<'a> Scanner<'a> {
-    pub fn new(source: &[u8]) -> Scanner {
+    /// Creates a scanner that skips whitespace and comments, other than doc comments.
+    pub fn new(source: &str) -> Scanner<'_> {
         Scanner {
             source,
             start: 0,
             current: 0,
+            start_line: 1,
             line: 1,
+            start_column: 1,
+            column: 1,
+            emit_trivia: false,
+            finished: false,
+        }
+    }
+
+    /// Creates a scanner that also produces tokens for whitespace and comments, namely
+    /// [`TokenKind::Whitespace`], [`TokenKind::LineComment`] and [`TokenKind::BlockComment`], so
+    /// that the source can be reconstructed exactly from the lexemes of the tokens.
+    pub fn with_trivia(source: &str) -> Scanner<'_> {
+        Scanner {
+            emit_trivia: true,
+            ..Scanner::new(source)
         }
     }
 
+    /// Scans the next token. Once the end of the source is reached, every call returns a token of
+    /// kind [`TokenKind::Eof`].
     pub fn scan_token(&mut self) -> Token<'a> {
-        self.skip_whitespace();
-        self.start = self.current;
+        if let Some(token) = self.skip_whitespace() {
+            return token;
+        }
+        self.start_token();
 
         if self.is_at_end() {
             return self.make_token(TokenKind::Eof);
@@ -26,50 +71,57 @@ impl<'a> Scanner<'a> {
         let c = self.advance();
 
         match c {
-            b'(' => self.make_token(TokenKind::LeftParen),
-            b')' => self.make_token(TokenKind::RightParen),
-            b'{' => self.make_token(TokenKind::LeftBrace),
-            b'}' => self.make_token(TokenKind::LeftBrace),
-            b';' => self.make_token(TokenKind::Semicolon),
-            b',' => self.make_token(TokenKind::Comma),
-            b'.' => self.make_token(TokenKind::Dot),
-            b'-' => self.make_token(TokenKind::Minus),
-            b'+' => self.make_token(TokenKind::Plus),
-            b'/' => self.make_token(TokenKind::Slash),
-            b'*' => self.make_token(TokenKind::Star),
-            b'!' => {
-                let matches = self.matches(b'=');
+            '(' => self.make_token(TokenKind::LeftParen),
+            ')' => self.make_token(TokenKind::RightParen),
+            '{' => self.make_token(TokenKind::LeftBrace),
+            '}' => self.make_token(TokenKind::RightBrace),
+            ';' => self.make_token(TokenKind::Semicolon),
+            ',' => self.make_token(TokenKind::Comma),
+            '.' => self.make_token(TokenKind::Dot),
+            '-' => self.make_token(TokenKind::Minus),
+            '+' => self.make_token(TokenKind::Plus),
+            '/' => {
+                // skip_whitespace() only stops at a "//" if it starts a doc comment.
+                if self.peek() == '/' {
+                    self.doc_comment()
+                } else {
+                    self.make_token(TokenKind::Slash)
+                }
+            }
+            '*' => self.make_token(TokenKind::Star),
+            '!' => {
+                let matches = self.matches('=');
                 self.make_token(if matches {
                     TokenKind::BangEqual
                 } else {
                     TokenKind::Bang
                 })
             }
-            b'=' => {
-                let matches = self.matches(b'=');
+            '=' => {
+                let matches = self.matches('=');
                 self.make_token(if matches {
                     TokenKind::EqualEqual
                 } else {
                     TokenKind::Equal
                 })
             }
-            b'<' => {
-                let matches = self.matches(b'=');
+            '<' => {
+                let matches = self.matches('=');
                 self.make_token(if matches {
                     TokenKind::LessEqual
                 } else {
                     TokenKind::Less
                 })
             }
-            b'>' => {
-                let matches = self.matches(b'=');
+            '>' => {
+                let matches = self.matches('=');
                 self.make_token(if matches {
                     TokenKind::GreaterEqual
                 } else {
                     TokenKind::Greater
                 })
             }
-            b'"' => self.string(),
+            '"' => self.string(),
             c if self.is_alpha(c) => self.identifier(),
             c if self.is_digit(c) => self.number(),
             _ => self.error_token("Unexpected character."),
@@ -80,96 +132,187 @@ impl<'a> Scanner<'a> {
         self.current == self.source.len()
     }
 
-    fn is_alpha(&self, c: u8) -> bool {
-        (b'a'..=b'z').contains(&c) || (b'A'..=b'Z').contains(&c) || c == b'_'
+    // Identifiers follow the Unicode XID rules (as used by Rust, among other languages), extended
+    // to allow a leading `_`.
+    fn is_alpha(&self, c: char) -> bool {
+        c == '_' || c.is_xid_start()
     }
 
-    fn is_digit(&self, c: u8) -> bool {
-        (b'0'..=b'9').contains(&c)
+    fn is_alphanumeric(&self, c: char) -> bool {
+        c.is_xid_continue()
     }
 
-    fn advance(&mut self) -> u8 {
-        self.current += 1;
-        self.source[self.current - 1]
+    fn is_digit(&self, c: char) -> bool {
+        c.is_ascii_digit()
     }
 
-    fn peek(&self) -> u8 {
-        if self.is_at_end() {
-            return b'\0';
-        }
-        self.source[self.current]
+    fn advance(&mut self) -> char {
+        let c = self.peek();
+        self.current += c.len_utf8();
+        self.column = if c == '\n' { 1 } else { self.column + 1 };
+        c
     }
 
-    fn peek_next(&self) -> u8 {
-        if self.is_at_end() {
-            return b'\0';
-        }
-        self.source[self.current + 1]
+    fn peek(&self) -> char {
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
-    fn matches(&mut self, expected: u8) -> bool {
+    fn peek_next(&self) -> char {
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
+    }
+
+    fn matches(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
         }
         if self.peek() != expected {
             return false;
         }
-        self.current += 1;
+        self.advance();
         true
     }
 
+    fn start_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+    }
+
     fn make_token(&self, kind: TokenKind) -> Token<'a> {
-        // let length = self.current - self.start;
         Token {
             kind,
             lexeme: &self.source[self.start..self.current],
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.span(),
         }
     }
 
     fn error_token(&self, message: &'static str) -> Token<'static> {
         Token {
             kind: TokenKind::Error,
-            lexeme: message.as_bytes(),
-            line: self.line,
+            lexeme: message,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.span(),
+        }
+    }
+
+    fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
         }
     }
 
-    fn skip_whitespace(&mut self) {
+    // Skips whitespace and comments, except for doc comments, which are scanned as tokens.
+    //
+    // Returns the next token if it has to be produced while skipping, which is the case for
+    // unterminated block comments and for whitespace and comments when trivia is being emitted.
+    fn skip_whitespace(&mut self) -> Option<Token<'a>> {
         loop {
-            let c = self.peek();
-            match c {
-                b' ' => {
+            self.start_token();
+            let kind = match (self.peek(), self.peek_next()) {
+                (' ' | '\r' | '\t' | '\n', _) => {
+                    self.whitespace();
+                    TokenKind::Whitespace
+                }
+                ('/', '/') if !self.is_at_doc_comment() => {
+                    // A comment goes until the end of the line.
+                    while self.peek() != '\n' && !self.is_at_end() {
+                        self.advance();
+                    }
+                    TokenKind::LineComment
+                }
+                ('/', '*') => {
+                    if let Some(error) = self.block_comment() {
+                        return Some(error);
+                    }
+                    TokenKind::BlockComment
+                }
+                _ => return None,
+            };
+
+            if self.emit_trivia {
+                return Some(self.make_token(kind));
+            }
+        }
+    }
+
+    fn whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                ' ' | '\r' | '\t' => {
+                    self.advance();
+                }
+                '\n' => {
+                    self.line += 1;
                     self.advance();
                 }
-                b'\r' => {
+                _ => return,
+            }
+        }
+    }
+
+    // Doc comments start with exactly three slashes, so that "////" separators are still treated
+    // as regular comments.
+    fn is_at_doc_comment(&self) -> bool {
+        let rest = &self.source[self.current..];
+        rest.starts_with("///") && !rest.starts_with("////")
+    }
+
+    // Block comments can be nested, like in:
+    //
+    //   /* outer /* inner */ still outer */
+    //
+    // ...which makes it possible to comment out code that already contains block comments.
+    fn block_comment(&mut self) -> Option<Token<'a>> {
+        // Consume the opening "/*".
+        self.advance();
+        self.advance();
+
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Some(self.error_token("Unterminated block comment."));
+            }
+
+            match (self.peek(), self.peek_next()) {
+                ('/', '*') => {
                     self.advance();
+                    self.advance();
+                    depth += 1;
                 }
-                b'\t' => {
+                ('*', '/') => {
+                    self.advance();
                     self.advance();
+                    depth -= 1;
                 }
-                b'\n' => {
+                ('\n', _) => {
                     self.line += 1;
                     self.advance();
                 }
-                b'/' => {
-                    if self.peek_next() == b'/' {
-                        // A comment goes until the end of the line.
-                        while self.peek() != b'\n' && !self.is_at_end() {
-                            self.advance();
-                        }
-                    } else {
-                        return;
-                    }
+                _ => {
+                    self.advance();
                 }
-                _ => return,
             }
         }
+
+        None
+    }
+
+    // Unlike other comments, doc comments are kept as tokens, so that tooling can attach them to
+    // the declarations that follow them. The lexeme includes the leading "///".
+    fn doc_comment(&mut self) -> Token<'a> {
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+        self.make_token(TokenKind::DocComment)
     }
 
     fn string(&mut self) -> Token<'a> {
-        while self.peek() != b'"' && !self.is_at_end() {
-            if self.peek() == b'\n' {
+        while self.peek() != '"' && !self.is_at_end() {
+            if self.peek() == '\n' {
                 self.line += 1;
             }
             self.advance();
@@ -185,20 +328,22 @@ impl<'a> Scanner<'a> {
     }
 
     fn identifier(&mut self) -> Token<'a> {
-        while self.is_alpha(self.peek()) || self.is_digit(self.peek()) {
+        while self.is_alphanumeric(self.peek()) {
             self.advance();
         }
         self.make_token(self.identifier_kind())
     }
 
     fn identifier_kind(&self) -> TokenKind {
-        match self.source[self.start] {
+        // All keywords are ASCII, so it's safe to look at the individual bytes of the identifier.
+        let source = self.source.as_bytes();
+        match source[self.start] {
             b'a' => self.check_keyword(1, 2, b"nd", TokenKind::And),
             b'c' => self.check_keyword(1, 4, b"lass", TokenKind::Class),
             b'e' => self.check_keyword(1, 3, b"lse", TokenKind::Else),
             b'f' => {
                 if self.current - self.start > 1 {
-                    return match self.source[self.start + 1] {
+                    return match source[self.start + 1] {
                         b'a' => self.check_keyword(2, 3, b"lse", TokenKind::False),
                         b'o' => self.check_keyword(2, 1, b"r", TokenKind::For),
                         b'u' => self.check_keyword(2, 1, b"n", TokenKind::Fun),
@@ -215,7 +360,7 @@ impl<'a> Scanner<'a> {
             b's' => self.check_keyword(1, 4, b"uper", TokenKind::Super),
             b't' => {
                 if self.current - self.start > 1 {
-                    return match self.source[self.start + 1] {
+                    return match source[self.start + 1] {
                         b'h' => self.check_keyword(2, 2, b"is", TokenKind::This),
                         b'r' => self.check_keyword(2, 2, b"ue", TokenKind::True),
                         _ => TokenKind::Identifier,
@@ -237,7 +382,7 @@ impl<'a> Scanner<'a> {
         kind: TokenKind,
     ) -> TokenKind {
         if self.current - self.start == start + length
-            && self.source[(self.start + start)..(self.start + start + length)] == *rest
+            && self.source.as_bytes()[(self.start + start)..(self.start + start + length)] == *rest
         {
             return kind;
         }
@@ -245,56 +390,100 @@ impl<'a> Scanner<'a> {
         TokenKind::Identifier
     }
 
+    // Scans decimal (`123`, `123.45`, `1e-9`), hexadecimal (`0x1F`) and binary (`0b1010`) number
+    // literals, all of which may contain `_` digit separators (`1_000_000`).
+    //
+    // Any letters, digits or underscores that directly follow the literal are consumed as part of
+    // it, so that malformed literals like `0b102`, `1e` or `12abc` are scanned as a single token.
+    // The compiler is responsible for validating the lexeme and reporting malformed literals.
     fn number(&mut self) -> Token<'a> {
-        while self.is_digit(self.peek()) {
-            self.advance();
-        }
+        let is_decimal = !matches!(self.peek(), 'x' | 'X' | 'b' | 'B')
+            || !self.source[self.start..].starts_with('0');
+
+        self.number_part(is_decimal);
 
         // Look for a fractional part.
-        if self.peek() == b'.' && self.is_digit(self.peek_next()) {
+        if self.peek() == '.' && self.is_digit(self.peek_next()) {
             // Consume the ".".
             self.advance();
 
-            while self.is_digit(self.peek()) {
+            self.number_part(is_decimal);
+        }
+
+        self.make_token(TokenKind::Number)
+    }
+
+    fn number_part(&mut self, is_decimal: bool) {
+        loop {
+            let c = self.peek();
+            if is_decimal && (c == 'e' || c == 'E') && matches!(self.peek_next(), '+' | '-') {
+                // Consume the "e" and the sign of the exponent.
+                self.advance();
+                self.advance();
+            } else if self.is_alphanumeric(c) {
                 self.advance();
+            } else {
+                return;
             }
         }
+    }
+}
 
-        self.make_token(TokenKind::Number)
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let token = self.scan_token();
+        self.finished = token.kind == TokenKind::Eof;
+        Some(token)
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Hash)]
-pub(crate) struct Token<'a> {
+/// A token scanned from Lox source code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Token<'a> {
     pub kind: TokenKind,
-    pub lexeme: &'a [u8],
+    /// The source text of the token, or the error message for tokens of kind [`TokenKind::Error`].
+    pub lexeme: &'a str,
+    /// The line on which the token starts, counting from 1.
     pub line: usize,
+    /// The column at which the token starts, counting from 1. Columns are counted in chars rather
+    /// than bytes.
+    pub column: usize,
+    /// The location of the token in the source. For error tokens, this covers the source text
+    /// that caused the error.
+    pub span: Span,
 }
 
-impl<'a> Token<'a> {
-    pub fn lexeme_to_string(&self) -> String {
-        // The lexeme came into the VM from the source file, which is read as a Rust string, so the
-        // lexeme is guaranteed to be UTF-8.
-        String::from_utf8_lossy(self.lexeme).to_string() // TODO: is .to_string() needed?
-    }
+/// A range of byte offsets into the source code, from `start` inclusive to `end` exclusive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
-    pub fn string_literal_lexeme_to_string(&self) -> String {
-        let length = &self.lexeme.len() - 2;
-        let string_literal = &self.lexeme[1..length];
-        // The lexeme came into the VM from the source file, which is read as a Rust string, so the
-        // lexeme is guaranteed to be UTF-8.
-        String::from_utf8_lossy(string_literal).to_string() // TODO: is .to_string() needed?
+impl<'a> Token<'a> {
+    pub(crate) fn string_literal_lexeme_to_string(&self) -> String {
+        // Strip the surrounding quotes, which are always one byte each.
+        self.lexeme[1..self.lexeme.len() - 1].to_string()
     }
 }
 
 pub(crate) const NULL_TOKEN: Token = Token {
     kind: TokenKind::Error,
-    lexeme: &[],
+    lexeme: "",
     line: 0,
+    column: 0,
+    span: Span { start: 0, end: 0 },
 };
 
-#[derive(Copy, Clone, Debug, PartialEq, Hash)]
-pub(crate) enum TokenKind {
+/// The kind of a [`Token`]. New kinds may be added as the language grows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TokenKind {
     // Single-character tokens.
     LeftParen,
     RightParen,
@@ -337,8 +526,208 @@ pub(crate) enum TokenKind {
     True,
     Var,
     While,
+    // Comments.
+    DocComment,
+    // Trivia, which is only produced by Scanner::with_trivia().
+    Whitespace,
+    LineComment,
+    BlockComment,
     // Error.
     Error,
     // End of file.
     Eof,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token<'_>> {
+        Scanner::new(source).collect()
+    }
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let source = "été + \"日本\";";
+        let spans: Vec<_> = tokens(source).iter().map(|token| token.span).collect();
+        assert_eq!(
+            spans,
+            [
+                span(0, 5),
+                span(6, 7),
+                span(8, 16),
+                span(16, 17),
+                span(17, 17)
+            ]
+        );
+        for token in tokens(source) {
+            assert_eq!(&source[token.span.start..token.span.end], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn reconstructs_the_source_from_trivia() {
+        let source =
+            "/// Doc.\nprint 1 +  // Line.\n\t/* Block /* nested */\n*/ \"a\r\nb\"; ////\n";
+        let tokens: Vec<_> = Scanner::with_trivia(source).collect();
+        let lexemes: String = tokens.iter().map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, source);
+        for pair in tokens.windows(2) {
+            assert_eq!(pair[0].span.end, pair[1].span.start);
+        }
+        assert!(tokens.iter().all(|token| token.kind != TokenKind::Error));
+    }
+
+    #[test]
+    fn yields_eof_exactly_once() {
+        for source in ["", "1 + 2", "// Comment.", "/* Unterminated."] {
+            for scanner in [Scanner::new(source), Scanner::with_trivia(source)] {
+                let kinds: Vec<_> = scanner.map(|token| token.kind).collect();
+                let eofs = kinds.iter().filter(|&&kind| kind == TokenKind::Eof).count();
+                assert_eq!(eofs, 1, "{source:?}");
+                assert_eq!(kinds.last(), Some(&TokenKind::Eof), "{source:?}");
+            }
+        }
+
+        // scan_token() keeps returning Eof, but the iterator stops after it.
+        let mut scanner = Scanner::new("1");
+        assert_eq!(scanner.next().unwrap().kind, TokenKind::Number);
+        assert_eq!(scanner.next().unwrap().kind, TokenKind::Eof);
+        assert_eq!(scanner.next(), None);
+        assert_eq!(scanner.scan_token().kind, TokenKind::Eof);
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn error_tokens_span_the_text_that_caused_them() {
+        let cases = [
+            ("1 @ 2", "Unexpected character.", span(2, 3)),
+            ("1 € 2", "Unexpected character.", span(2, 5)),
+            ("print \"abc", "Unterminated string.", span(6, 10)),
+            ("1 /* a /* b */", "Unterminated block comment.", span(2, 14)),
+        ];
+        for (source, message, expected) in cases {
+            let error = tokens(source)
+                .into_iter()
+                .find(|token| token.kind == TokenKind::Error)
+                .unwrap();
+            assert_eq!(error.lexeme, message);
+            assert_eq!(error.span, expected, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn scans_unicode_identifiers() {
+        let cases = [
+            ("été", "été"),
+            ("_naïve1", "_naïve1"),
+            // An e followed by a combining acute accent.
+            ("e\u{301}t\u{301} ", "e\u{301}t\u{301}"),
+            ("変数+", "変数"),
+        ];
+        for (source, lexeme) in cases {
+            let token = tokens(source)[0];
+            assert_eq!(token.kind, TokenKind::Identifier, "{source:?}");
+            assert_eq!(token.lexeme, lexeme);
+        }
+
+        // A combining mark can continue an identifier, but not start one.
+        let kinds: Vec<_> = tokens("\u{301}e").iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            [TokenKind::Error, TokenKind::Identifier, TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn counts_columns_in_chars() {
+        let positions: Vec<_> = tokens("été = \"日本\" + e\u{301};\n  変数 x")
+            .iter()
+            .map(|token| (token.lexeme, token.line, token.column))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                ("été", 1, 1),
+                ("=", 1, 5),
+                ("\"日本\"", 1, 7),
+                ("+", 1, 12),
+                ("e\u{301}", 1, 14),
+                (";", 1, 16),
+                ("変数", 2, 3),
+                ("x", 2, 6),
+                ("", 2, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unterminated_block_comments_on_the_line_they_start() {
+        let tokens = tokens("1\n/* a /* b */\nc");
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1].kind, TokenKind::Error);
+        assert_eq!(tokens[1].lexeme, "Unterminated block comment.");
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 1));
+        assert_eq!(tokens[2].kind, TokenKind::Eof);
+        assert_eq!(tokens[2].line, 3);
+    }
+
+    #[test]
+    fn counts_lines_in_block_comments() {
+        let tokens = tokens("/* a\nb /* c\n */\n*/ x\n/**/ y");
+        assert_eq!(
+            (tokens[0].lexeme, tokens[0].line, tokens[0].column),
+            ("x", 4, 4)
+        );
+        assert_eq!(
+            (tokens[1].lexeme, tokens[1].line, tokens[1].column),
+            ("y", 5, 6)
+        );
+    }
+
+    #[test]
+    fn scans_only_three_slashes_as_a_doc_comment() {
+        let kinds = |source| {
+            Scanner::with_trivia(source)
+                .map(|token| (token.kind, token.lexeme))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds("/// Doc.\n"),
+            [
+                (TokenKind::DocComment, "/// Doc."),
+                (TokenKind::Whitespace, "\n"),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            kinds("///"),
+            [(TokenKind::DocComment, "///"), (TokenKind::Eof, "")]
+        );
+        assert_eq!(
+            kinds("//// Separator."),
+            [
+                (TokenKind::LineComment, "//// Separator."),
+                (TokenKind::Eof, "")
+            ]
+        );
+        assert_eq!(
+            kinds("// Comment."),
+            [
+                (TokenKind::LineComment, "// Comment."),
+                (TokenKind::Eof, "")
+            ]
+        );
+
+        // Without trivia, only the doc comment is kept.
+        let kinds: Vec<_> = tokens("/// Doc.\n//// Separator.\n// Comment.")
+            .iter()
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(kinds, [TokenKind::DocComment, TokenKind::Eof]);
+    }
+}
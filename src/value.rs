@@ -1,18 +1,71 @@
+// With the nan_boxing feature, values are NaN-boxed into a single u64 instead, like clox does
+// with NAN_BOXING. Both representations have the same API, so the rest of the crate should create
+// and inspect values with the methods below rather than by matching on the enum's variants.
+#[cfg(feature = "nan_boxing")]
+mod nan_boxing;
+#[cfg(feature = "nan_boxing")]
+pub use nan_boxing::Value;
+
+#[cfg(not(feature = "nan_boxing"))]
 use std::fmt::{Display, Formatter};
+#[cfg(not(feature = "nan_boxing"))]
+use std::rc::Rc;
 
+#[cfg(not(feature = "nan_boxing"))]
 #[derive(Clone, Debug, PartialEq)]
-// TODO: Is it possible to allow Value::Strings to
-//       share the same String with Rc? If so, add
-//       Copy again.
+// Strings are shared with Rc, so that copying a value onto the stack doesn't copy its string.
+// TODO: Is it possible to make Value Copy again?
 #[repr(C)] // TODO: Is this needed?
 pub enum Value {
     Bool(bool),
     Nil,
     Number(f64),
-    String(String),
+    String(Rc<str>),
 }
 
+#[cfg(not(feature = "nan_boxing"))]
 impl Value {
+    pub const fn nil() -> Value {
+        Value::Nil
+    }
+
+    pub fn bool(value: bool) -> Value {
+        Value::Bool(value)
+    }
+
+    pub fn number(value: f64) -> Value {
+        Value::Number(value)
+    }
+
+    pub fn string(value: String) -> Value {
+        Value::String(Rc::from(value))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
     pub fn is_falsey(&self) -> bool {
         match self {
             Value::Nil => true,
@@ -22,6 +75,7 @@ impl Value {
     }
 }
 
+#[cfg(not(feature = "nan_boxing"))]
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -0,0 +1,296 @@
+// NaN boxing packs every value into the 64 bits of an f64, following the "Optimization" chapter
+// of Crafting Interpreters [1].
+//
+// A double is a NaN when all of its exponent bits are set, and a quiet NaN when the highest bit of
+// its mantissa is set too. Real arithmetic only ever produces one or two particular NaNs, which
+// leaves the other 50 or so bits of every quiet NaN free to store something else:
+//
+// - Numbers are stored as themselves. Any NaN is stored as the canonical NaN, so that it can't be
+//   mistaken for one of the values below.
+// - nil, false and true are quiet NaNs with 1, 2 or 3 in their lowest bits.
+// - Objects are quiet NaNs with the sign bit set, and a pointer to the object in the lowest 48 bits,
+//   which is as many bits as 64-bit platforms use for addresses in practice.
+//
+// Strings are the only objects so far, so an object is always a reference-counted String.
+// Cloning a value shares its string, and dropping the last value that refers to a string frees it.
+//
+// As the reference counts aren't atomic, values must stay on the thread that created them, so
+// Value is neither Send nor Sync, and nor is anything that holds values, like Chunk. The enum
+// representation shares strings with an Rc too, so it has the same auto traits.
+//
+// [1] https://craftinginterpreters.com/optimization.html#nan-boxing
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+#[cfg(not(target_pointer_width = "64"))]
+compile_error!("The nan_boxing feature needs pointers to fit in the low bits of a NaN.");
+
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+
+const TAG_NIL: u64 = 1;
+const TAG_FALSE: u64 = 2;
+const TAG_TRUE: u64 = 3;
+
+const NIL_VALUE: u64 = QNAN | TAG_NIL;
+const FALSE_VALUE: u64 = QNAN | TAG_FALSE;
+const TRUE_VALUE: u64 = QNAN | TAG_TRUE;
+
+// The marker stands in for the Rc that an object's bits hold, which makes the compiler treat Value
+// as neither Send nor Sync, like Rc. On its own, a u64 field would make it both.
+pub struct Value(u64, PhantomData<Rc<String>>);
+
+impl Value {
+    const fn from_bits(bits: u64) -> Value {
+        Value(bits, PhantomData)
+    }
+
+    pub const fn nil() -> Value {
+        Value::from_bits(NIL_VALUE)
+    }
+
+    pub fn bool(value: bool) -> Value {
+        Value::from_bits(if value { TRUE_VALUE } else { FALSE_VALUE })
+    }
+
+    pub fn number(value: f64) -> Value {
+        if value.is_nan() {
+            Value::from_bits(f64::NAN.to_bits())
+        } else {
+            Value::from_bits(value.to_bits())
+        }
+    }
+
+    pub fn string(value: String) -> Value {
+        let pointer = Rc::into_raw(Rc::new(value)) as u64;
+        assert_eq!(
+            pointer & (SIGN_BIT | QNAN),
+            0,
+            "String address doesn't fit in a NaN-boxed value."
+        );
+        Value::from_bits(SIGN_BIT | QNAN | pointer)
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == NIL_VALUE
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.0 {
+            TRUE_VALUE => Some(true),
+            FALSE_VALUE => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        if self.0 & QNAN != QNAN {
+            Some(f64::from_bits(self.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        // SAFETY: The pointer came from Rc::into_raw() in Value::string(), and this value holds
+        // one of the string's strong references, so the string is still alive.
+        self.as_object()
+            .map(|pointer| unsafe { (*pointer).as_str() })
+    }
+
+    pub fn is_falsey(&self) -> bool {
+        self.is_nil() || self.0 == FALSE_VALUE
+    }
+
+    fn as_object(&self) -> Option<*const String> {
+        if self.0 & (SIGN_BIT | QNAN) == SIGN_BIT | QNAN {
+            Some((self.0 & !(SIGN_BIT | QNAN)) as *const String)
+        } else {
+            None
+        }
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        if let Some(pointer) = self.as_object() {
+            // SAFETY: See as_str(). The new value holds the new strong reference.
+            unsafe { Rc::increment_strong_count(pointer) };
+        }
+        Value::from_bits(self.0)
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        if let Some(pointer) = self.as_object() {
+            // SAFETY: See as_str(). This releases the strong reference that this value held.
+            unsafe { Rc::decrement_strong_count(pointer) };
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        // Numbers are compared as numbers, so that NaN isn't equal to itself, and strings are
+        // compared by their contents, as they aren't interned.
+        match (self.as_number(), other.as_number()) {
+            (Some(a), Some(b)) => return a == b,
+            (None, None) => {}
+            _ => return false,
+        }
+        match (self.as_str(), other.as_str()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.0 == other.0,
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(number) = self.as_number() {
+            f.debug_tuple("Number").field(&number).finish()
+        } else if let Some(boolean) = self.as_bool() {
+            f.debug_tuple("Bool").field(&boolean).finish()
+        } else if let Some(string) = self.as_str() {
+            f.debug_tuple("String").field(&string).finish()
+        } else {
+            write!(f, "Nil")
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(number) = self.as_number() {
+            write!(f, "{number}")
+        } else if let Some(boolean) = self.as_bool() {
+            write!(f, "{boolean}")
+        } else if let Some(string) = self.as_str() {
+            write!(f, "{string}")
+        } else {
+            write!(f, "nil")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::ManuallyDrop;
+
+    // Returns the number of values that share the string in the given value.
+    fn strong_count(value: &Value) -> usize {
+        let pointer = value.as_object().expect("value should be a string");
+        // SAFETY: See as_str(). The Rc is never dropped, so it doesn't release this value's
+        // reference.
+        let string = ManuallyDrop::new(unsafe { Rc::from_raw(pointer) });
+        Rc::strong_count(&string)
+    }
+
+    #[test]
+    fn numbers_survive_boxing() {
+        for number in [0.0, 1.5, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY] {
+            assert_eq!(Value::number(number).as_number(), Some(number));
+            assert_eq!(Value::number(-number).as_number(), Some(-number));
+        }
+        let negative_zero = Value::number(-0.0).as_number().unwrap();
+        assert!(negative_zero == 0.0 && negative_zero.is_sign_negative());
+    }
+
+    #[test]
+    fn nans_are_canonicalized() {
+        // Quiet NaNs whose payloads look like nil, true and a string pointer.
+        for bits in [
+            NIL_VALUE,
+            TRUE_VALUE,
+            SIGN_BIT | QNAN | 0x1000,
+            f64::NAN.to_bits(),
+        ] {
+            let value = Value::number(f64::from_bits(bits));
+            assert!(value.as_number().unwrap().is_nan());
+            assert_eq!(value.as_bool(), None);
+            assert_eq!(value.as_str(), None);
+            assert!(!value.is_nil());
+            assert_ne!(value, value.clone());
+        }
+    }
+
+    #[test]
+    fn singletons_are_not_numbers_or_strings() {
+        for value in [Value::nil(), Value::bool(false), Value::bool(true)] {
+            assert_eq!(value.as_number(), None);
+            assert_eq!(value.as_str(), None);
+        }
+        assert!(Value::nil().is_nil());
+        assert_eq!(Value::nil().as_bool(), None);
+        assert_eq!(Value::bool(false).as_bool(), Some(false));
+        assert_eq!(Value::bool(true).as_bool(), Some(true));
+        assert!(Value::nil().is_falsey());
+        assert!(Value::bool(false).is_falsey());
+        assert!(!Value::bool(true).is_falsey());
+        assert!(!Value::number(0.0).is_falsey());
+    }
+
+    #[test]
+    fn clones_share_their_string() {
+        let value = Value::string("shared".to_string());
+        assert_eq!(strong_count(&value), 1);
+
+        let clone = value.clone();
+        assert_eq!(strong_count(&value), 2);
+        assert_eq!(value.0, clone.0);
+
+        drop(value);
+        assert_eq!(strong_count(&clone), 1);
+        assert_eq!(clone.as_str(), Some("shared"));
+        assert_eq!(clone.as_number(), None);
+        assert_eq!(clone.as_bool(), None);
+        assert!(!clone.is_nil() && !clone.is_falsey());
+    }
+
+    #[test]
+    fn values_of_different_types_are_not_equal() {
+        let values = [
+            Value::nil(),
+            Value::bool(false),
+            Value::bool(true),
+            Value::number(0.0),
+            Value::number(1.0),
+            Value::string(String::new()),
+            Value::string("true".to_string()),
+        ];
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert_eq!(a == b, i == j, "{a:?} == {b:?}");
+            }
+        }
+        assert_eq!(Value::number(0.0), Value::number(-0.0));
+        assert_eq!(
+            Value::string("lox".to_string()),
+            Value::string("lox".to_string())
+        );
+    }
+
+    #[test]
+    fn formats_values() {
+        let cases = [
+            (Value::nil(), "nil", "Nil"),
+            (Value::bool(true), "true", "Bool(true)"),
+            (Value::number(-0.0), "-0", "Number(-0.0)"),
+            (Value::number(2.5), "2.5", "Number(2.5)"),
+            (Value::number(f64::NAN), "NaN", "Number(NaN)"),
+            (
+                Value::string("a\"b".to_string()),
+                "a\"b",
+                "String(\"a\\\"b\")",
+            ),
+        ];
+        for (value, display, debug) in cases {
+            assert_eq!(value.to_string(), display);
+            assert_eq!(format!("{value:?}"), debug);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Checks that a chunk of bytecode is safe for the VM to run.
+//
+// The VM trusts its bytecode: it doesn't check opcodes, constant indices or that its stack has
+// enough values to pop as it runs. That's fine for chunks that the compiler has just produced, but
+// chunks loaded from elsewhere, like from a .loxc file, have to be verified first. The VM does
+// check that its stack doesn't overflow, as the limit on its size is configurable.
+//
+// There are no jump instructions yet, so execution always runs straight through a chunk until
+// its first OP_RETURN. That makes it possible to track the exact depth of the stack before each
+// instruction. Once jumps exist, their targets will need to be checked to land on the start of an
+// instruction, and the depths from every path into an instruction will need to agree.
+
+use crate::chunk::{Chunk, OpCode};
+use std::fmt::{Display, Formatter};
+use std::{error, fmt};
+
+/// Checks that the given chunk is well-formed, so that the VM can run it without crashing.
+pub fn verify(chunk: &Chunk) -> Result<(), VerifyError> {
+    if chunk.line_table_len() != chunk.code.len() {
+        return Err(VerifyError::LineTableMismatch);
+    }
+
+    let mut depth = 0;
+    let mut returned = false;
+    for instruction in chunk.instructions() {
+        let offset = instruction.offset;
+        let Some(op_code) = instruction.op_code else {
+            return Err(VerifyError::UnknownOpcode {
+                offset,
+                byte: instruction.byte,
+            });
+        };
+        if instruction.operands.len() != op_code.operand_count() {
+            return Err(VerifyError::TruncatedInstruction { offset });
+        }
+        if let (Some(index), None) = (instruction.constant_index(), instruction.constant) {
+            return Err(VerifyError::InvalidConstant { offset, index });
+        }
+
+        // Instructions after the first OP_RETURN are never executed, so the stack doesn't matter
+        // to them.
+        if returned {
+            continue;
+        }
+        let (pops, pushes) = op_code.stack_effect();
+        if depth < pops {
+            return Err(VerifyError::StackUnderflow { offset });
+        }
+        depth = depth - pops + pushes;
+        returned = op_code == OpCode::Return;
+    }
+
+    if !returned {
+        return Err(VerifyError::MissingReturn);
+    }
+
+    Ok(())
+}
+
+/// The reasons that a chunk can fail verification.
+#[derive(Copy, Clone, Debug, PartialEq, Hash)]
+pub enum VerifyError {
+    LineTableMismatch,
+    UnknownOpcode { offset: usize, byte: u8 },
+    TruncatedInstruction { offset: usize },
+    InvalidConstant { offset: usize, index: usize },
+    StackUnderflow { offset: usize },
+    MissingReturn,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::LineTableMismatch => {
+                write!(f, "The line table doesn't match the length of the code.")
+            }
+            VerifyError::UnknownOpcode { offset, byte } => {
+                write!(f, "Unknown opcode {byte} at offset {offset}.")
+            }
+            VerifyError::TruncatedInstruction { offset } => {
+                write!(f, "Instruction at offset {offset} is missing operands.")
+            }
+            VerifyError::InvalidConstant { offset, index } => write!(
+                f,
+                "Instruction at offset {offset} refers to constant {index}, which doesn't exist."
+            ),
+            VerifyError::StackUnderflow { offset } => write!(
+                f,
+                "Instruction at offset {offset} pops more values than are on the stack."
+            ),
+            VerifyError::MissingReturn => write!(f, "Code never reaches an OP_RETURN."),
+        }
+    }
+}
+
+impl error::Error for VerifyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::value::Value;
+
+    // Returns a chunk with the given code, all on line 1, and the given constants.
+    fn chunk(code: &[u8], constants: &[f64]) -> Chunk {
+        let mut chunk = Chunk::new();
+        for &byte in code {
+            chunk.write_byte(byte, 1);
+        }
+        for &constant in constants {
+            chunk.constants.push(Value::number(constant));
+        }
+        chunk
+    }
+
+    const CONSTANT: u8 = OpCode::Constant as u8;
+    const CONSTANT_LONG: u8 = OpCode::ConstantLong as u8;
+    const NIL: u8 = OpCode::Nil as u8;
+    const ADD: u8 = OpCode::Add as u8;
+    const NEGATE: u8 = OpCode::Negate as u8;
+    const RETURN: u8 = OpCode::Return as u8;
+
+    #[test]
+    fn accepts_compiled_chunks() {
+        let chunk = compile("!(1 == 2) != \"a\" + 1").unwrap();
+        assert_eq!(verify(&chunk), Ok(()));
+    }
+
+    #[test]
+    fn accepts_well_formed_chunks() {
+        let code = [CONSTANT, 0, CONSTANT_LONG, 1, 0, 0, ADD, NEGATE, RETURN];
+        assert_eq!(verify(&chunk(&code, &[1.0, 2.0])), Ok(()));
+    }
+
+    #[test]
+    fn ignores_the_stack_after_the_first_return() {
+        assert_eq!(verify(&chunk(&[NIL, RETURN, ADD], &[])), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_line_table_that_doesnt_match_the_code() {
+        let mut chunk = chunk(&[NIL, RETURN], &[]);
+        chunk.code.push(RETURN);
+        assert_eq!(verify(&chunk), Err(VerifyError::LineTableMismatch));
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes() {
+        assert_eq!(
+            verify(&chunk(&[NIL, 255, RETURN], &[])),
+            Err(VerifyError::UnknownOpcode {
+                offset: 1,
+                byte: 255
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_instructions_that_are_missing_operands() {
+        assert_eq!(
+            verify(&chunk(&[CONSTANT], &[1.0])),
+            Err(VerifyError::TruncatedInstruction { offset: 0 })
+        );
+        assert_eq!(
+            verify(&chunk(&[NIL, RETURN, CONSTANT_LONG, 0, 0], &[1.0])),
+            Err(VerifyError::TruncatedInstruction { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn rejects_constants_that_dont_exist() {
+        assert_eq!(
+            verify(&chunk(&[CONSTANT, 1, RETURN], &[1.0])),
+            Err(VerifyError::InvalidConstant {
+                offset: 0,
+                index: 1
+            })
+        );
+        assert_eq!(
+            verify(&chunk(&[CONSTANT_LONG, 0, 0, 1, RETURN], &[1.0])),
+            Err(VerifyError::InvalidConstant {
+                offset: 0,
+                index: 0x10000
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_instructions_that_pop_more_values_than_are_on_the_stack() {
+        assert_eq!(
+            verify(&chunk(&[NIL, ADD, RETURN], &[])),
+            Err(VerifyError::StackUnderflow { offset: 1 })
+        );
+        assert_eq!(
+            verify(&chunk(&[RETURN], &[])),
+            Err(VerifyError::StackUnderflow { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn rejects_code_that_never_returns() {
+        assert_eq!(verify(&chunk(&[], &[])), Err(VerifyError::MissingReturn));
+        assert_eq!(
+            verify(&chunk(&[NIL, NEGATE], &[])),
+            Err(VerifyError::MissingReturn)
+        );
+    }
+}
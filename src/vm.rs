@@ -1,50 +1,196 @@
 use crate::chunk::{Chunk, OpCode};
-use crate::compiler::Compiler;
+use crate::compiler::compile;
 use crate::value::Value;
+use crate::verifier::verify;
+use std::io;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
 
-const STACK_MAX: usize = 256;
+// The default limit on the number of values on the stack. This is what clox's stack ends up
+// being able to hold once it has call frames: 64 frames of 256 slots each.
+const STACK_MAX: usize = 64 * 256;
+// The stack starts out this big when the first value is pushed, and grows as needed.
+const STACK_INITIAL_CAPACITY: usize = 256;
+// How many instructions are run between checks of the deadline. Reading the clock is much slower
+// than running an instruction, so it isn't done for every one.
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+/// Configures how a VM runs its script.
+pub struct Config<'a> {
+    /// Where to write the disassembly of the script's bytecode before running it, if anywhere.
+    pub print_code: Option<Box<dyn io::Write + 'a>>,
+    /// Where to write the stack and each instruction as they are executed, if anywhere.
+    pub trace_execution: Option<Box<dyn io::Write + 'a>>,
+    /// The number of instructions after which the VM stops with
+    /// InterpretError::InterpretBudgetExceeded, if any. This protects against scripts that would
+    /// otherwise never finish.
+    pub max_steps: Option<usize>,
+    /// How long the VM can run a script for before it stops with
+    /// InterpretError::InterpretBudgetExceeded, if there's a limit. The clock is only checked every
+    /// so often, so the VM can overrun the limit slightly.
+    pub time_limit: Option<Duration>,
+    /// The most values that the stack can hold. Pushing any more stops the VM with a "Stack
+    /// overflow." runtime error.
+    pub max_stack_size: usize,
+    /// The most bytes of memory that the VM can allocate while running a script, if there's a
+    /// limit. Going over it stops the VM with InterpretError::InterpretOutOfMemory, after
+    /// reporting an "Out of memory." runtime error. The script's bytecode and constants don't count
+    /// towards it.
+    ///
+    /// For now, the only memory that the VM allocates is the storage for the stack. Strings that
+    /// are pushed onto the stack share their contents with the constants they come from.
+    ///
+    /// Compiling isn't capped: interpret() compiles the VM's source before the limit applies, so
+    /// the memory taken by the compiler, and the chunk it produces, grows with the size of the
+    /// source. When sandboxing untrusted scripts, limit the size of their source too.
+    pub max_heap_size: Option<usize>,
+}
+
+impl Default for Config<'_> {
+    // The debug_print_code and debug_trace_execution cargo features turn the corresponding
+    // output on by default, writing it to stdout.
+    fn default() -> Self {
+        Config {
+            print_code: cfg!(feature = "debug_print_code")
+                .then(|| Box::new(io::stdout()) as Box<dyn io::Write>),
+            trace_execution: cfg!(feature = "debug_trace_execution")
+                .then(|| Box::new(io::stdout()) as Box<dyn io::Write>),
+            max_steps: None,
+            time_limit: None,
+            max_stack_size: STACK_MAX,
+            max_heap_size: None,
+        }
+    }
+}
 
 pub struct VM<'a> {
-    source: &'a [u8],
+    source: &'a str,
+    config: Config<'a>,
     chunk: Chunk,
     ip: usize,
-    stack: [Value; STACK_MAX],
+    // The slots at stack_top and above aren't on the stack. Values that are popped are left in
+    // their slots until they're overwritten, which saves dropping them.
+    stack: Vec<Value>,
     stack_top: usize,
+    // The number of instructions that the VM may still run, and when it must stop running them, if
+    // there are limits. Rather than checking both before every instruction, run() counts down
+    // from the number of instructions that can safely be run before checking them again.
+    steps_remaining: Option<usize>,
+    deadline: Option<Instant>,
+    countdown: usize,
+    countdown_start: usize,
+    // Whether the VM stopped running its script because it ran out of budget, so can resume it.
+    suspended: bool,
 }
 
-impl VM<'_> {
-    pub fn new(source: &str) -> VM {
+impl<'a> VM<'a> {
+    pub fn new(source: &str) -> VM<'_> {
+        VM::with_config(source, Config::default())
+    }
+
+    pub fn with_config(source: &'a str, config: Config<'a>) -> VM<'a> {
         let chunk = Chunk::new();
         let ip = 0;
-        const INIT: Value = Value::Number(0f64);
-        const SIZE: usize = 256;
-        let stack = [INIT; SIZE];
-        let stack_top = 0;
+        let stack = Vec::new();
         VM {
-            // TODO: Converting to bytes here is forcing us to have to extract substrings that are
-            //       heap-allocated, whenever we need to print errors or extract Tokens of kind
-            //       TokenKind::Number.
-            //       Is there any way we can work with &str throughout the interpreter, rather than
-            //       &[u8]?
-            source: source.as_bytes(),
+            source,
+            config,
             chunk,
             ip,
             stack,
-            stack_top,
+            stack_top: 0,
+            steps_remaining: None,
+            deadline: None,
+            countdown: 0,
+            countdown_start: 0,
+            suspended: false,
         }
     }
 
     pub fn interpret(&mut self) -> Result<(), InterpretError> {
-        let mut chunk = Chunk::new();
+        // Whatever script was stopped before is abandoned, even if this one doesn't compile.
+        self.suspended = false;
+        let Some(chunk) = compile(self.source) else {
+            return Err(InterpretError::InterpretCompileError);
+        };
 
-        if !Compiler::new(self.source, &mut chunk).compile() {
+        self.run_chunk(chunk)
+    }
+
+    /// Runs an already-compiled chunk, like one loaded from a .loxc file, rather than the VM's
+    /// source code.
+    ///
+    /// The chunk is verified first, as it may not come from a trusted compiler. If it fails
+    /// verification, the reason is reported to stderr and nothing is run.
+    pub fn interpret_chunk(&mut self, chunk: Chunk) -> Result<(), InterpretError> {
+        // See interpret().
+        self.suspended = false;
+        if let Err(error) = verify(&chunk) {
+            eprintln!("Invalid bytecode: {error}");
             return Err(InterpretError::InterpretCompileError);
         }
 
+        self.run_chunk(chunk)
+    }
+
+    fn run_chunk(&mut self, chunk: Chunk) -> Result<(), InterpretError> {
+        if let Some(out) = &mut self.config.print_code {
+            let _ = chunk.disassemble("code", out);
+        }
+
         self.chunk = chunk;
         self.ip = 0;
+        self.stack.clear();
+        self.stack_top = 0;
+        self.steps_remaining = self.config.max_steps;
+        self.deadline = self
+            .config
+            .time_limit
+            .map(|time_limit| Instant::now() + time_limit);
+
+        self.run_until_stopped()
+    }
+
+    /// Continues running a script that stopped with InterpretError::InterpretBudgetExceeded, from
+    /// the instruction that it stopped at. Give the VM more budget first, with set_step_budget()
+    /// or set_deadline(), or it will stop again straight away.
+    ///
+    /// This lets a host run scripts a slice at a time, switching between them whenever one runs
+    /// out of budget.
+    ///
+    /// Panics if the VM isn't stopped in the middle of a script.
+    pub fn resume(&mut self) -> Result<(), InterpretError> {
+        assert!(self.suspended, "There is no script to resume.");
+        self.run_until_stopped()
+    }
 
-        self.run()
+    /// Sets how many more instructions the VM may run, or None to let it run any number of them.
+    /// This replaces Config::max_steps, or what's left of it, for the script being run.
+    pub fn set_step_budget(&mut self, steps: Option<usize>) {
+        self.steps_remaining = steps;
+    }
+
+    /// Sets when the VM must stop running, or None to let it run for as long as it takes. This
+    /// replaces the deadline given by Config::time_limit for the script being run.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    fn run_until_stopped(&mut self) -> Result<(), InterpretError> {
+        // Makes run() check the budget before running its first instruction.
+        self.countdown = 0;
+        self.countdown_start = 0;
+        self.suspended = false;
+
+        // Tracing is decided once, up front, so that the dispatch loop doesn't pay for checking
+        // it on every instruction when it's off.
+        let result = if self.config.trace_execution.is_some() {
+            self.run::<true>()
+        } else {
+            self.run::<false>()
+        };
+        self.suspended = result == Err(InterpretError::InterpretBudgetExceeded);
+        result
     }
 
     // The "beating heart" of the VM.
@@ -58,85 +204,130 @@ impl VM<'_> {
     // He says that in C, the fastest techniques would need non-standard
     // extensions to C or handwritten assembly code. This implies that in Rust,
     // unsafe code or some other technique would be needed.
-    fn run(&mut self) -> Result<(), InterpretError> {
+    //
+    // Note: with benches/dispatch.rs, neither decoding the chunk into an array of instructions
+    // before running it nor dispatching through a table of function pointers beat the match
+    // below, which compiles to a jump table. Decoding costs as much as it saves while code only
+    // runs once, as there are no loops yet, and calls through function pointers can't be inlined.
+    // What made a difference was not moving values around the stack needlessly: instructions
+    // that pop their operands and push a result replace their first operand with the result
+    // instead.
+    fn run<const TRACE_EXECUTION: bool>(&mut self) -> Result<(), InterpretError> {
         loop {
-            #[cfg(feature = "debug_trace_execution")]
-            self.trace_execution();
+            // The budget is checked before an instruction is read, so that the VM can resume from
+            // it.
+            if self.countdown == 0 && !self.restart_countdown() {
+                return Err(InterpretError::InterpretBudgetExceeded);
+            }
+            self.countdown -= 1;
+
+            if TRACE_EXECUTION {
+                self.trace_execution();
+            }
 
             let instruction = self.read_byte();
+
             let op_code = OpCode::from_u8(instruction);
             match op_code {
                 Some(OpCode::Constant) => {
                     let constant = self.read_constant();
-                    self.push(constant);
+                    self.push(constant)?;
+                }
+                Some(OpCode::ConstantLong) => {
+                    let constant = self.read_constant_long();
+                    self.push(constant)?;
                 }
                 Some(OpCode::Nil) => {
-                    self.push(Value::Nil);
+                    self.push(Value::nil())?;
                 }
                 Some(OpCode::True) => {
-                    self.push(Value::Bool(true));
+                    self.push(Value::bool(true))?;
                 }
                 Some(OpCode::False) => {
-                    self.push(Value::Bool(false));
+                    self.push(Value::bool(false))?;
                 }
                 Some(OpCode::Equal) => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(Value::Bool(a == b));
+                    let equal = self.peek(1) == self.peek(0);
+                    self.stack_top -= 1;
+                    self.replace_top(Value::bool(equal));
+                }
+                Some(OpCode::NotEqual) => {
+                    let not_equal = self.peek(1) != self.peek(0);
+                    self.stack_top -= 1;
+                    self.replace_top(Value::bool(not_equal));
                 }
                 Some(OpCode::Greater) => {
                     self.binary_op(
                         #[inline]
                         |a, b| a > b,
-                        Value::Bool,
+                        Value::bool,
+                    )?;
+                }
+                Some(OpCode::GreaterEqual) => {
+                    self.binary_op(
+                        #[inline]
+                        |a, b| a >= b,
+                        Value::bool,
                     )?;
                 }
                 Some(OpCode::Less) => {
                     self.binary_op(
                         #[inline]
                         |a, b| a < b,
-                        Value::Bool,
+                        Value::bool,
+                    )?;
+                }
+                Some(OpCode::LessEqual) => {
+                    self.binary_op(
+                        #[inline]
+                        |a, b| a <= b,
+                        Value::bool,
                     )?;
                 }
                 Some(OpCode::Add) => {
                     self.binary_op(
                         #[inline]
                         |a, b| a + b,
-                        Value::Number,
+                        Value::number,
                     )?;
                 }
+                Some(OpCode::AddConstant) => {
+                    let index = self.read_byte() as usize;
+                    let constant = self.chunk.constants[index].as_number();
+                    match (self.peek(0).as_number(), constant) {
+                        (Some(a), Some(b)) => self.replace_top(Value::number(a + b)),
+                        _ => return Err(self.operands_error()),
+                    }
+                }
                 Some(OpCode::Subtract) => {
                     self.binary_op(
                         #[inline]
                         |a, b| a - b,
-                        Value::Number,
+                        Value::number,
                     )?;
                 }
                 Some(OpCode::Multiply) => {
                     self.binary_op(
                         #[inline]
                         |a, b| a * b,
-                        Value::Number,
+                        Value::number,
                     )?;
                 }
                 Some(OpCode::Divide) => {
                     self.binary_op(
                         #[inline]
                         |a, b| a / b,
-                        Value::Number,
+                        Value::number,
                     )?;
                 }
                 Some(OpCode::Not) => {
-                    let value = self.pop();
-                    self.push(Value::Bool(value.is_falsey()))
+                    let falsey = self.peek(0).is_falsey();
+                    self.replace_top(Value::bool(falsey))
                 }
                 Some(OpCode::Negate) => {
-                    match self.peek(0) {
-                        Value::Number(number) => {
-                            self.pop();
-                            self.push(Value::Number(-number))
-                        }
-                        _ => {
+                    match self.peek(0).as_number() {
+                        Some(number) => self.replace_top(Value::number(-number)),
+                        None => {
                             // See [1].
                             self.runtime_error("Operand must be a number.");
                             return Err(InterpretError::InterpretRuntimeError);
@@ -148,47 +339,133 @@ impl VM<'_> {
                     return Ok(());
                 }
                 None => {
-                    panic!("Unknown opcode {instruction}");
+                    // The compiler never emits unknown opcodes, and the verifier rejects chunks
+                    // from elsewhere that contain them.
+                    unreachable!("Unknown opcode {instruction}");
                 }
             }
         }
     }
 
+    // Takes the instructions run since the countdown was last started out of the budget, then
+    // starts counting down to when it has to be checked again. Returns false if the VM has run out
+    // of steps or time instead.
+    #[cold]
+    fn restart_countdown(&mut self) -> bool {
+        let steps_run = self.countdown_start - self.countdown;
+        if let Some(steps_remaining) = &mut self.steps_remaining {
+            *steps_remaining -= steps_run;
+        }
+
+        if self.steps_remaining == Some(0)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return false;
+        }
+
+        let until_deadline_check = if self.deadline.is_some() {
+            DEADLINE_CHECK_INTERVAL
+        } else {
+            usize::MAX
+        };
+        self.countdown = self
+            .steps_remaining
+            .unwrap_or(usize::MAX)
+            .min(until_deadline_check);
+        self.countdown_start = self.countdown;
+        true
+    }
+
+    // Kept out of line, as it's rarely called, so that it doesn't get in the way of optimizing the
+    // instructions that report it.
+    #[cold]
+    fn operands_error(&self) -> InterpretError {
+        // See [1].
+        self.runtime_error("Operands must be numbers.");
+        InterpretError::InterpretRuntimeError
+    }
+
     #[inline]
     fn binary_op<T>(
         &mut self,
         op: fn(f64, f64) -> T,
         value_type: fn(T) -> Value,
     ) -> Result<(), InterpretError> {
-        match (self.peek(0), self.peek(1)) {
-            (Value::Number(b), Value::Number(a)) => {
-                self.pop();
-                self.pop();
-                self.push(value_type(op(a, b)));
+        match (self.peek(0).as_number(), self.peek(1).as_number()) {
+            (Some(b), Some(a)) => {
+                self.stack_top -= 1;
+                self.replace_top(value_type(op(a, b)));
                 Ok(())
             }
-            _ => {
-                // See [1].
-                self.runtime_error("Operands must be numbers.");
-                Err(InterpretError::InterpretRuntimeError)
-            }
+            _ => Err(self.operands_error()),
         }
     }
 
-    fn push(&mut self, constant: Value) {
-        self.stack[self.stack_top] = constant;
+    // Note: expressions that are nested deeply enough, and recursion once there are functions,
+    // can push more values than the stack is allowed to hold. That's reported as a runtime error
+    // rather than crashing the VM.
+    #[inline]
+    fn push(&mut self, constant: Value) -> Result<(), InterpretError> {
+        if self.stack_top < self.stack.len() {
+            self.stack[self.stack_top] = constant;
+            self.stack_top += 1;
+            Ok(())
+        } else {
+            self.grow_stack(constant)
+        }
+    }
+
+    // Pushes a value onto the stack when every slot is in use, which is rare enough to be kept
+    // out of push().
+    #[cold]
+    fn grow_stack(&mut self, constant: Value) -> Result<(), InterpretError> {
+        if self.stack_top >= self.config.max_stack_size {
+            self.runtime_error("Stack overflow.");
+            return Err(InterpretError::InterpretRuntimeError);
+        }
+        if self.stack.len() == self.stack.capacity() && !self.reserve_stack() {
+            self.runtime_error("Out of memory.");
+            return Err(InterpretError::InterpretOutOfMemory);
+        }
+        self.stack.push(constant);
         self.stack_top += 1;
+        Ok(())
     }
 
+    // Makes room for more values on the stack, doubling its capacity like a Vec would, but without
+    // going over the heap limit, or aborting the process when the system is out of memory.
+    // Returns false if there's no room.
+    fn reserve_stack(&mut self) -> bool {
+        let capacity = self.stack.capacity();
+        let mut new_capacity = (capacity * 2).max(STACK_INITIAL_CAPACITY);
+        if let Some(max_heap_size) = self.config.max_heap_size {
+            new_capacity = new_capacity.min(max_heap_size / size_of::<Value>());
+        }
+        new_capacity > capacity
+            && self
+                .stack
+                .try_reserve_exact(new_capacity - self.stack.len())
+                .is_ok()
+    }
+
+    // The compiler, and the verifier for chunks from elsewhere, make sure that instructions never
+    // pop more values than are on the stack.
     fn pop(&mut self) -> Value {
         self.stack_top -= 1;
         // TODO: Remove .clone() when Value is Copy-able
         self.stack[self.stack_top].clone()
     }
 
-    fn peek(&self, distance: usize) -> Value {
-        // TODO: Remove .clone() when Value is Copy-able
-        self.stack[self.stack_top - 1 - distance].clone()
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack_top - 1 - distance]
+    }
+
+    // Replaces the value on top of the stack. Instructions that pop their operands and push a
+    // result do this instead, which saves moving their operands off the stack.
+    fn replace_top(&mut self, value: Value) {
+        self.stack[self.stack_top - 1] = value;
     }
 
     fn read_byte(&mut self) -> u8 {
@@ -203,22 +480,32 @@ impl VM<'_> {
         self.chunk.constants[byte as usize].clone()
     }
 
+    fn read_constant_long(&mut self) -> Value {
+        let index =
+            u32::from_le_bytes([self.read_byte(), self.read_byte(), self.read_byte(), 0]) as usize;
+        self.chunk.constants[index].clone()
+    }
+
     fn runtime_error(&self, message: &str) {
         eprintln!("{message}");
-        let line = self.chunk.lines[self.ip - 1];
+        let line = self.chunk.get_line(self.ip - 1);
         eprintln!("[line {line}] in script");
     }
 
-    #[cfg(feature = "debug_trace_execution")]
-    fn trace_execution(&self) {
-        print!("          ");
-        for slot in self.stack.iter().take(self.stack_top) {
-            print!("[ {slot} ]");
+    fn trace_execution(&mut self) {
+        let Some(out) = &mut self.config.trace_execution else {
+            return;
+        };
+        // Tracing is best-effort, so failures to write the trace are ignored.
+        let _ = write!(out, "          ");
+        for slot in &self.stack[..self.stack_top] {
+            let _ = write!(out, "[ {slot} ]");
         }
-        println!();
-        self.chunk
+        let _ = writeln!(out);
+        let _ = self
+            .chunk
             // TODO: Is the commented-out code below needed? It causes panics for 'true' and 'false'
-            .disassemble_instruction(self.ip /*- self.chunk.code[0] as usize*/);
+            .disassemble_instruction(self.ip /*- self.chunk.code[0] as usize*/, out);
     }
 }
 
@@ -226,7 +513,196 @@ impl VM<'_> {
 pub enum InterpretError {
     InterpretCompileError,
     InterpretRuntimeError,
+    /// The VM ran out of steps or time before the script finished. Unlike the other errors, the
+    /// script can be continued with VM::resume().
+    InterpretBudgetExceeded,
+    /// The script needed more memory than Config::max_heap_size allows, or than the system could
+    /// give it.
+    InterpretOutOfMemory,
 }
 
 // [1] Stopping the program on a runtime error, without giving the user any control on what happens,
 //     is not ideal, so in a real language, this would be changed.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns a chunk that adds up the given number of ones, which runs 2 * count instructions.
+    fn sum_of_ones(count: usize) -> Chunk {
+        let mut chunk = Chunk::new();
+        for _ in 0..count {
+            chunk.write_constant(Value::number(1.0), 1);
+        }
+        for _ in 1..count {
+            chunk.write_op_code(OpCode::Add, 1);
+        }
+        chunk.write_op_code(OpCode::Return, 1);
+        chunk
+    }
+
+    fn vm(config: Config<'_>) -> VM<'_> {
+        VM::with_config("", config)
+    }
+
+    // Returns the value that the last script run by the VM returned. OP_RETURN leaves it in its
+    // slot when it pops it.
+    fn returned_value(vm: &VM) -> Value {
+        vm.stack[vm.stack_top].clone()
+    }
+
+    #[test]
+    fn compares_nan_with_numbers() {
+        let cases = [
+            (OpCode::Equal, false),
+            (OpCode::NotEqual, true),
+            (OpCode::Greater, false),
+            (OpCode::GreaterEqual, false),
+            (OpCode::Less, false),
+            (OpCode::LessEqual, false),
+        ];
+        let mut vm = vm(Config::default());
+        for (op_code, expected) in cases {
+            for operands in [[f64::NAN, 1.0], [1.0, f64::NAN], [f64::NAN, f64::NAN]] {
+                let mut chunk = Chunk::new();
+                for operand in operands {
+                    chunk.write_constant(Value::number(operand), 1);
+                }
+                chunk.write_op_code(op_code, 1);
+                chunk.write_op_code(OpCode::Return, 1);
+                assert_eq!(vm.interpret_chunk(chunk), Ok(()));
+                assert_eq!(
+                    returned_value(&vm),
+                    Value::bool(expected),
+                    "{op_code:?} {operands:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn loads_constants_past_the_first_256() {
+        let mut chunk = Chunk::new();
+        for number in 0..300 {
+            assert_eq!(
+                chunk.write_constant(Value::number(number as f64), 1),
+                Some(number)
+            );
+        }
+        for _ in 1..300 {
+            chunk.write_op_code(OpCode::Add, 1);
+        }
+        chunk.write_op_code(OpCode::Return, 1);
+
+        let loads: Vec<_> = chunk.instructions().take(300).collect();
+        assert!(loads[..256]
+            .iter()
+            .all(|load| load.op_code == Some(OpCode::Constant)));
+        for (index, load) in loads.iter().enumerate().skip(256) {
+            assert_eq!(load.op_code, Some(OpCode::ConstantLong));
+            assert_eq!(load.constant_index(), Some(index));
+            assert_eq!(load.constant, Some(&Value::number(index as f64)));
+        }
+        assert_eq!(
+            loads[299].to_string(),
+            "0684    1 OP_CONSTANT_LONG  299 '299'"
+        );
+
+        let mut vm = vm(Config::default());
+        assert_eq!(vm.interpret_chunk(chunk), Ok(()));
+        assert_eq!(
+            returned_value(&vm),
+            Value::number((0..300).sum::<i32>() as f64)
+        );
+    }
+
+    #[test]
+    fn stops_when_out_of_steps_and_resumes_with_more() {
+        let mut vm = vm(Config {
+            max_steps: Some(15),
+            ..Config::default()
+        });
+        let mut result = vm.interpret_chunk(sum_of_ones(20));
+        let mut slices = 1;
+        while result == Err(InterpretError::InterpretBudgetExceeded) {
+            vm.set_step_budget(Some(10));
+            result = vm.resume();
+            slices += 1;
+        }
+        assert_eq!(result, Ok(()));
+        assert_eq!(slices, 4);
+    }
+
+    #[test]
+    fn runs_exactly_as_many_steps_as_allowed() {
+        let mut vm = vm(Config {
+            max_steps: Some(40),
+            ..Config::default()
+        });
+        assert_eq!(vm.interpret_chunk(sum_of_ones(20)), Ok(()));
+        vm.config.max_steps = Some(39);
+        assert_eq!(
+            vm.interpret_chunk(sum_of_ones(20)),
+            Err(InterpretError::InterpretBudgetExceeded)
+        );
+    }
+
+    #[test]
+    fn stops_at_the_deadline() {
+        let mut vm = vm(Config {
+            time_limit: Some(Duration::ZERO),
+            ..Config::default()
+        });
+        assert_eq!(
+            vm.interpret_chunk(sum_of_ones(20)),
+            Err(InterpretError::InterpretBudgetExceeded)
+        );
+        vm.set_deadline(None);
+        assert_eq!(vm.resume(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "There is no script to resume.")]
+    fn forgets_a_stopped_script_when_another_fails_to_verify() {
+        let mut vm = vm(Config {
+            max_steps: Some(1),
+            ..Config::default()
+        });
+        assert_eq!(
+            vm.interpret_chunk(sum_of_ones(20)),
+            Err(InterpretError::InterpretBudgetExceeded)
+        );
+        assert_eq!(
+            vm.interpret_chunk(Chunk::new()),
+            Err(InterpretError::InterpretCompileError)
+        );
+        let _ = vm.resume();
+    }
+
+    #[test]
+    fn runs_out_of_memory_when_the_stack_outgrows_the_heap_limit() {
+        let max_heap_size = 100 * size_of::<Value>();
+        let mut vm = vm(Config {
+            max_heap_size: Some(max_heap_size),
+            ..Config::default()
+        });
+        assert_eq!(vm.interpret_chunk(sum_of_ones(100)), Ok(()));
+        assert_eq!(
+            vm.interpret_chunk(sum_of_ones(101)),
+            Err(InterpretError::InterpretOutOfMemory)
+        );
+    }
+
+    #[test]
+    fn overflows_the_stack_before_running_out_of_memory() {
+        let mut vm = vm(Config {
+            max_stack_size: 10,
+            max_heap_size: Some(10 * size_of::<Value>()),
+            ..Config::default()
+        });
+        assert_eq!(
+            vm.interpret_chunk(sum_of_ones(11)),
+            Err(InterpretError::InterpretRuntimeError)
+        );
+    }
+}
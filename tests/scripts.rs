@@ -0,0 +1,18 @@
+// Runs the Lox scripts in the test directory with the `test` command, which checks their output
+// against their `// expect: ` comments.
+
+use std::process::Command;
+
+#[test]
+fn scripts_behave_as_expected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-clox"))
+        .arg("test")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/test"))
+        .output()
+        .expect("the interpreter should run");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}